@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE IF EXISTS "reminder_target";
+
+ALTER TABLE "guild"
+      DROP COLUMN remind_others_perms;
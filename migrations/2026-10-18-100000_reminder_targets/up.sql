@@ -0,0 +1,18 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "reminder_target" (
+       reminder_id BIGINT NOT NULL REFERENCES reminder (id) ON DELETE CASCADE,
+       target_id BIGINT NOT NULL,
+       is_role BOOLEAN NOT NULL DEFAULT false,
+       PRIMARY KEY (reminder_id, target_id)
+);
+
+-- existing reminders only ever reminded their creator
+INSERT INTO "reminder_target" (reminder_id, target_id)
+       SELECT id, user_id FROM "reminder";
+
+CREATE INDEX IF NOT EXISTS "reminder_target_target_id_idx" ON "reminder_target" ("target_id");
+
+-- permission bits needed to remind other people, defaults to manage messages
+ALTER TABLE "guild"
+      ADD COLUMN remind_others_perms BIGINT NOT NULL DEFAULT 8192;
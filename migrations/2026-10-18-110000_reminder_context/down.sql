@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "reminder"
+      DROP COLUMN message_id,
+      DROP COLUMN guild_id;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+ALTER TABLE "reminder"
+      ADD COLUMN message_id BIGINT,
+      ADD COLUMN guild_id BIGINT;
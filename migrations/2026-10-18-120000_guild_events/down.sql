@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE IF EXISTS "guild_event_ping";
+DROP TABLE IF EXISTS "guild_event_rsvp";
+DROP TABLE IF EXISTS "guild_event";
+
+ALTER TABLE "guild"
+      DROP COLUMN event_lead_times;
@@ -0,0 +1,30 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "guild_event" (
+       id BIGSERIAL PRIMARY KEY,
+       guild_id BIGINT NOT NULL REFERENCES guild (id) ON DELETE CASCADE,
+       channel_id BIGINT NOT NULL,
+       message_id BIGINT NOT NULL UNIQUE,
+       author_id BIGINT NOT NULL,
+       title VARCHAR(2000) NOT NULL,
+       "when" TIMESTAMP NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS "guild_event_rsvp" (
+       event_id BIGINT NOT NULL REFERENCES guild_event (id) ON DELETE CASCADE,
+       user_id BIGINT NOT NULL,
+       PRIMARY KEY (event_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS "guild_event_ping" (
+       id BIGSERIAL PRIMARY KEY,
+       event_id BIGINT NOT NULL REFERENCES guild_event (id) ON DELETE CASCADE,
+       "when" TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "guild_event_guild_id_when_idx" ON "guild_event" ("guild_id", "when");
+CREATE INDEX IF NOT EXISTS "guild_event_ping_when_idx" ON "guild_event_ping" ("when" ASC);
+
+-- minutes before an event that attendees are pinged
+ALTER TABLE "guild"
+      ADD COLUMN event_lead_times INTEGER[] NOT NULL DEFAULT '{1440, 15}';
@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "announcement" (
+       id BIGSERIAL PRIMARY KEY,
+       guild_id BIGINT NOT NULL REFERENCES guild (id) ON DELETE CASCADE,
+       channel_id BIGINT NOT NULL,
+       author_id BIGINT NOT NULL,
+       text VARCHAR(2000) NOT NULL,
+       is_embed BOOLEAN NOT NULL DEFAULT FALSE,
+       next_run TIMESTAMP NOT NULL,
+       -- minutes between posts, NULL for one-off announcements
+       repeat_minutes INTEGER,
+       paused BOOLEAN NOT NULL DEFAULT FALSE
+);
+
+CREATE INDEX IF NOT EXISTS "announcement_guild_id_idx" ON "announcement" ("guild_id");
+CREATE INDEX IF NOT EXISTS "announcement_next_run_idx" ON "announcement" ("next_run" ASC) WHERE NOT paused;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+-- previous contents of a tag, saved whenever it is edited
+CREATE TABLE IF NOT EXISTS "tag_revision" (
+       id BIGSERIAL PRIMARY KEY,
+       tag_id BIGINT NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+       text VARCHAR(2000) NOT NULL,
+       replaced_by BIGINT NOT NULL,
+       replaced_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "tag_revision_tag_id_idx" ON "tag_revision" ("tag_id");
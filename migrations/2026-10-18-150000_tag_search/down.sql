@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS "tag_text_trgm_idx";
+DROP INDEX IF EXISTS "tag_key_trgm_idx";
+
+DROP EXTENSION IF EXISTS pg_trgm;
@@ -0,0 +1,6 @@
+-- Your SQL goes here
+
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX IF NOT EXISTS "tag_key_trgm_idx" ON "tag" USING GIN ("key" gin_trgm_ops);
+CREATE INDEX IF NOT EXISTS "tag_text_trgm_idx" ON "tag" USING GIN ("text" gin_trgm_ops);
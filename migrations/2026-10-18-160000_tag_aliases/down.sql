@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DELETE FROM "tag" WHERE alias_of IS NOT NULL;
+
+ALTER TABLE "tag"
+      DROP COLUMN alias_of;
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- aliases are rows in the tag table so they share the per-guild key constraint,
+-- their text is unused and they are deleted with the tag they point to
+ALTER TABLE "tag"
+      ADD COLUMN alias_of BIGINT REFERENCES tag (id) ON DELETE CASCADE;
+
+CREATE INDEX IF NOT EXISTS "tag_alias_of_idx" ON "tag" ("alias_of");
@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+DROP INDEX IF EXISTS "tag_guild_id_uses_idx";
+
+ALTER TABLE "tag"
+      DROP COLUMN uses,
+      DROP COLUMN last_used,
+      DROP COLUMN created_at;
@@ -0,0 +1,12 @@
+-- Your SQL goes here
+
+ALTER TABLE "tag"
+      ADD COLUMN uses INTEGER NOT NULL DEFAULT 0,
+      ADD COLUMN last_used TIMESTAMP,
+      ADD COLUMN created_at TIMESTAMP;
+
+-- existing tags have no known creation time
+ALTER TABLE "tag"
+      ALTER COLUMN created_at SET DEFAULT (now() AT TIME ZONE 'utc');
+
+CREATE INDEX IF NOT EXISTS "tag_guild_id_uses_idx" ON "tag" ("guild_id", "uses" DESC);
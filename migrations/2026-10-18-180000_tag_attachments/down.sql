@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+DROP TABLE IF EXISTS "tag_attachment";
+
+ALTER TABLE "tag"
+      DROP COLUMN embed;
@@ -0,0 +1,14 @@
+-- Your SQL goes here
+
+-- JSON embed definition sent along with the tag
+ALTER TABLE "tag"
+      ADD COLUMN embed VARCHAR(8000);
+
+CREATE TABLE IF NOT EXISTS "tag_attachment" (
+       id BIGSERIAL PRIMARY KEY,
+       tag_id BIGINT NOT NULL REFERENCES tag (id) ON DELETE CASCADE,
+       filename VARCHAR(255) NOT NULL,
+       data BYTEA NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS "tag_attachment_tag_id_idx" ON "tag_attachment" ("tag_id");
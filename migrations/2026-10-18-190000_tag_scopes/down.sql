@@ -0,0 +1,11 @@
+-- This file should undo anything in `up.sql`
+
+DELETE FROM "tag" WHERE scope <> 'guild';
+
+DROP INDEX IF EXISTS "tag_global_key_idx";
+DROP INDEX IF EXISTS "tag_personal_key_idx";
+
+ALTER TABLE "tag"
+      DROP CONSTRAINT "tag_scope_guild_id_check",
+      ALTER COLUMN guild_id SET NOT NULL,
+      DROP COLUMN scope;
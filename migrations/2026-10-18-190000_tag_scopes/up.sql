@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- personal tags belong to their author and global tags are curated by the bot owner,
+-- neither belong to a guild
+ALTER TABLE "tag"
+      ADD COLUMN scope VARCHAR(8) NOT NULL DEFAULT 'guild' CHECK (scope IN ('guild', 'personal', 'global'));
+
+ALTER TABLE "tag"
+      ALTER COLUMN guild_id DROP NOT NULL,
+      ADD CONSTRAINT "tag_scope_guild_id_check" CHECK ((scope = 'guild') = (guild_id IS NOT NULL));
+
+CREATE UNIQUE INDEX IF NOT EXISTS "tag_personal_key_idx" ON "tag" ("author_id", "key") WHERE scope = 'personal';
+CREATE UNIQUE INDEX IF NOT EXISTS "tag_global_key_idx" ON "tag" ("key") WHERE scope = 'global';
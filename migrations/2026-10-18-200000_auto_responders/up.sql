@@ -0,0 +1,17 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "auto_responder" (
+       id BIGSERIAL PRIMARY KEY,
+       guild_id BIGINT NOT NULL REFERENCES guild (id) ON DELETE CASCADE,
+       author_id BIGINT NOT NULL,
+       pattern VARCHAR(500) NOT NULL,
+       -- how the pattern is matched: 'literal', 'word' or 'regex'
+       match_kind VARCHAR(10) NOT NULL CHECK (match_kind IN ('literal', 'word', 'regex')),
+       case_sensitive BOOLEAN NOT NULL DEFAULT FALSE,
+       response VARCHAR(2000) NOT NULL,
+       -- channels the responder is limited to, empty for every channel
+       channel_ids BIGINT[] NOT NULL DEFAULT '{}',
+       cooldown_seconds INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS "auto_responder_guild_id_idx" ON "auto_responder" ("guild_id");
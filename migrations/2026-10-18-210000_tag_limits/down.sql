@@ -0,0 +1,6 @@
+-- This file should undo anything in `up.sql`
+
+ALTER TABLE "guild"
+      DROP COLUMN IF EXISTS tag_max_per_user,
+      DROP COLUMN IF EXISTS tag_max_per_guild,
+      DROP COLUMN IF EXISTS tag_create_role;
@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+-- limits on creating guild tags, NULL for no limit
+ALTER TABLE "guild"
+      ADD COLUMN tag_max_per_user INTEGER,
+      ADD COLUMN tag_max_per_guild INTEGER,
+      ADD COLUMN tag_create_role BIGINT;
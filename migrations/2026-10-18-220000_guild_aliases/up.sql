@@ -0,0 +1,10 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "guild_command_alias" (
+       id BIGSERIAL PRIMARY KEY,
+       guild_id BIGINT NOT NULL REFERENCES guild (id) ON DELETE CASCADE,
+       author_id BIGINT NOT NULL,
+       alias_name VARCHAR(2000) NOT NULL,
+       alias_value VARCHAR(2000) NOT NULL,
+       UNIQUE (guild_id, alias_name)
+);
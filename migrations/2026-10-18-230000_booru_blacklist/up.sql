@@ -0,0 +1,7 @@
+-- Your SQL goes here
+
+CREATE TABLE IF NOT EXISTS "booru_blacklist" (
+       guild_id BIGINT NOT NULL REFERENCES guild (id) ON DELETE CASCADE,
+       tag VARCHAR(200) NOT NULL,
+       PRIMARY KEY (guild_id, tag)
+);
@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- members reached through a role can't be removed from the targets, so they are skipped on delivery
+CREATE TABLE IF NOT EXISTS "reminder_opt_out" (
+       reminder_id BIGINT NOT NULL REFERENCES reminder (id) ON DELETE CASCADE,
+       user_id BIGINT NOT NULL,
+       PRIMARY KEY (reminder_id, user_id)
+);
@@ -1,15 +1,15 @@
-use chrono::{Duration, Utc};
+use chrono::{Duration, NaiveDateTime, Utc};
 // use dotenv;
-use models::Reminder;
+use models::{Announcement, GuildEvent, Reminder, ReminderTarget};
 // use reqwest;
 use serenity::{
-    model::id::{ChannelId, UserId},
+    model::id::{ChannelId, GuildId, RoleId, UserId},
     prelude::*,
     utils,
     utils::{shard_id, MessageBuilder},
 };
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     sync::{Once, ONCE_INIT},
     thread, time,
 };
@@ -18,6 +18,8 @@ use PgConnectionManager;
 
 static BOTLIST_UPDATE_START: Once = ONCE_INIT;
 static REMINDER_START: Once = ONCE_INIT;
+static EVENT_START: Once = ONCE_INIT;
+static ANNOUNCEMENT_START: Once = ONCE_INIT;
 
 pub fn background_task(ctx: &Context) {
     BOTLIST_UPDATE_START.call_once(|| {
@@ -81,7 +83,7 @@ pub fn background_task(ctx: &Context) {
     REMINDER_START.call_once(|| {
         use diesel;
         use diesel::prelude::*;
-        use schema::reminder;
+        use schema::{reminder, reminder_opt_out, reminder_target};
 
         let delay_period = Duration::seconds(10);
         let zero_duration = time::Duration::new(0, 0);
@@ -118,7 +120,18 @@ pub fn background_task(ctx: &Context) {
 
                     thread::sleep(diff);
 
-                    send_reminder_msg(&rem);
+                    let targets = reminder_target::dsl::reminder_target
+                        .filter(reminder_target::dsl::reminder_id.eq(rem.id))
+                        .load::<ReminderTarget>(pool)
+                        .unwrap_or_default();
+
+                    let opted_out = reminder_opt_out::dsl::reminder_opt_out
+                        .filter(reminder_opt_out::dsl::reminder_id.eq(rem.id))
+                        .select(reminder_opt_out::dsl::user_id)
+                        .load::<i64>(pool)
+                        .unwrap_or_default();
+
+                    send_reminder_msg(&rem, &targets, &opted_out);
 
                     diesel::delete(reminder::dsl::reminder.find(rem.id))
                         .execute(pool)
@@ -129,27 +142,303 @@ pub fn background_task(ctx: &Context) {
             thread::sleep(delay_period.to_std().unwrap());
         });
     });
+
+    EVENT_START.call_once(|| {
+        use diesel;
+        use diesel::prelude::*;
+        use schema::{guild_event, guild_event_ping, guild_event_rsvp};
+
+        let delay_period = Duration::seconds(10);
+        let zero_duration = time::Duration::new(0, 0);
+
+        let data = ctx.data.clone();
+
+        thread::spawn(move || loop {
+            debug!(target: "bot", "Event loop");
+
+            let now = Utc::now().naive_utc();
+            let time_limit = now + delay_period;
+
+            let pool = &*data
+                .lock()
+                .get::<PgConnectionManager>()
+                .unwrap()
+                .get()
+                .unwrap();
+
+            if let Ok(pings) = guild_event_ping::table
+                .inner_join(guild_event::table)
+                .filter(guild_event_ping::when.lt(time_limit))
+                .order(guild_event_ping::when)
+                .select((guild_event_ping::id, guild_event_ping::when, guild_event::all_columns))
+                .load::<(i64, NaiveDateTime, GuildEvent)>(pool)
+            {
+                if !pings.is_empty() {
+                    info!(target: "bot", "Collected {} event pings.", pings.len());
+                }
+
+                for (ping_id, ping_when, event) in pings {
+                    let diff = ping_when
+                        .signed_duration_since(Utc::now().naive_utc())
+                        .to_std()
+                        .unwrap_or(zero_duration);
+
+                    thread::sleep(diff);
+
+                    let attendees = guild_event_rsvp::table
+                        .filter(guild_event_rsvp::event_id.eq(event.id))
+                        .select(guild_event_rsvp::user_id)
+                        .load::<i64>(pool)
+                        .unwrap_or_default();
+
+                    send_event_ping(&event, ping_when, &attendees);
+
+                    diesel::delete(guild_event_ping::table.find(ping_id))
+                        .execute(pool)
+                        .unwrap();
+                }
+            }
+
+            // events that have started have had all their pings sent
+            diesel::delete(guild_event::table.filter(guild_event::when.lt(now)))
+                .execute(pool)
+                .unwrap();
+
+            thread::sleep(delay_period.to_std().unwrap());
+        });
+    });
+
+    ANNOUNCEMENT_START.call_once(|| {
+        use commands::announcements::next_run_after;
+        use diesel;
+        use diesel::prelude::*;
+        use schema::announcement;
+
+        let delay_period = Duration::seconds(10);
+        let zero_duration = time::Duration::new(0, 0);
+
+        let data = ctx.data.clone();
+
+        thread::spawn(move || loop {
+            debug!(target: "bot", "Announcement loop");
+
+            let time_limit = Utc::now().naive_utc() + delay_period;
+
+            let pool = &*data
+                .lock()
+                .get::<PgConnectionManager>()
+                .unwrap()
+                .get()
+                .unwrap();
+
+            if let Ok(announcements) = announcement::table
+                .filter(announcement::paused.eq(false))
+                .filter(announcement::next_run.lt(time_limit))
+                .order(announcement::next_run)
+                .load::<Announcement>(pool)
+            {
+                if !announcements.is_empty() {
+                    info!(target: "bot", "Collected {} announcements.", announcements.len());
+                }
+
+                for ann in announcements {
+                    let diff = ann
+                        .next_run
+                        .signed_duration_since(Utc::now().naive_utc())
+                        .to_std()
+                        .unwrap_or(zero_duration);
+
+                    thread::sleep(diff);
+
+                    send_announcement(&ann);
+
+                    match ann.repeat_minutes {
+                        Some(m) => {
+                            let next = next_run_after(ann.next_run, m, Utc::now().naive_utc());
+
+                            diesel::update(announcement::table.find(ann.id))
+                                .set(announcement::next_run.eq(next))
+                                .execute(pool)
+                                .unwrap();
+                        },
+                        None => {
+                            diesel::delete(announcement::table.find(ann.id))
+                                .execute(pool)
+                                .unwrap();
+                        },
+                    }
+                }
+            }
+
+            thread::sleep(delay_period.to_std().unwrap());
+        });
+    });
+}
+
+/// The members of a guild with a role
+fn role_members(g_id: i64, r_id: i64) -> Vec<i64> {
+    let role = RoleId::from(r_id as u64);
+
+    log_time!(utils::with_cache(|c| c.guild(GuildId::from(g_id as u64)).map_or_else(Vec::new, |g| g
+        .read()
+        .members
+        .values()
+        .filter(|m| m.roles.contains(&role))
+        .map(|m| m.user.read().id.0 as i64)
+        .collect())), "with_cache: role_members")
+}
+
+/// Work out who to mention for a reminder as (id, is_role) pairs.
+///
+/// Roles are expanded into their members when someone opted out, so they aren't pinged.
+fn reminder_mentions(rem: &Reminder, targets: &[ReminderTarget], opted_out: &[i64]) -> Vec<(i64, bool)> {
+    let mut mentions = Vec::new();
+
+    for t in targets {
+        if !t.is_role {
+            mentions.push((t.target_id, false));
+        } else if opted_out.is_empty() {
+            mentions.push((t.target_id, true));
+        } else if let Some(g_id) = rem.guild_id {
+            mentions.extend(role_members(g_id, t.target_id).into_iter().map(|u_id| (u_id, false)));
+        }
+    }
+
+    let mut seen = HashSet::new();
+    mentions.retain(|&(id, is_role)| (is_role || !opted_out.contains(&id)) && seen.insert((id, is_role)));
+
+    mentions
 }
 
-fn send_reminder_msg(rem: &Reminder) {
+fn send_reminder_msg(rem: &Reminder, targets: &[ReminderTarget], opted_out: &[i64]) {
     use commands::reminders::human_timedelta;
+    use itertools::Itertools;
+
+    let mentions = reminder_mentions(rem, targets, opted_out);
+
+    // everyone opted out of this reminder
+    if mentions.is_empty() {
+        return;
+    }
 
     let diff = rem.when.signed_duration_since(rem.started);
 
+    let only_creator = mentions
+        .iter()
+        .all(|&(id, is_role)| !is_role && id == rem.user_id);
+
     let content = MessageBuilder::new()
-        .user(rem.user_id as u64)
         .push(", ")
-        .push(human_timedelta(&diff))
-        .push(" ago, you asked me to remind you about: ")
-        .push_safe(&rem.text);
+        .push(human_timedelta(&diff));
+
+    let content = if only_creator {
+        content.push(" ago, you asked me to remind you about: ")
+    } else {
+        let creator = UserId::from(rem.user_id as u64)
+            .to_user()
+            .map(|u| u.name)
+            .unwrap_or_else(|_| "someone".to_owned());
+
+        content
+            .push(" ago, ")
+            .push_safe(creator)
+            .push(" asked me to remind you about: ")
+    };
+
+    let content = content.push_safe(&rem.text);
+
+    let content = match rem.message_id {
+        Some(m_id) => {
+            let guild = rem.guild_id.map_or_else(|| "@me".to_owned(), |g| g.to_string());
+
+            content
+                .push("\n")
+                .push(format!("https://discordapp.com/channels/{}/{}/{}", guild, rem.channel_id, m_id))
+        },
+        None => content,
+    }.build();
 
     let chan = ChannelId::from(rem.channel_id as u64);
-    if chan.say(&content).is_ok() {
-        return;
+
+    // expanded roles can have many members, keep each message under the length limit
+    let mut undelivered = Vec::new();
+
+    for chunk in &mentions.iter().chunks(50) {
+        let chunk: Vec<_> = chunk.collect();
+
+        let message = chunk.iter().enumerate().fold(MessageBuilder::new(), |m, (i, &&(id, is_role))| {
+            let m = if i > 0 { m.push(" ") } else { m };
+
+            if is_role {
+                m.role(id as u64)
+            } else {
+                m.user(id as u64)
+            }
+        }).push(&content);
+
+        // only the users mentioned by a message that failed are sent the reminder directly
+        if chan.say(&message).is_err() {
+            undelivered.extend(chunk.into_iter().filter(|&&(_, is_role)| !is_role).map(|&(id, _)| id));
+        }
+    }
+
+    for id in undelivered {
+        let user = UserId::from(id as u64);
+        if let Ok(chan) = user.create_dm_channel() {
+            void!(chan.say(MessageBuilder::new().user(user).push(&content)));
+        }
     }
+}
 
-    let user = UserId::from(rem.user_id as u64);
-    if let Ok(chan) = user.create_dm_channel() {
-        void!(chan.say(&content));
+
+fn send_event_ping(event: &GuildEvent, ping_when: NaiveDateTime, attendees: &[i64]) {
+    use commands::reminders::human_timedelta;
+    use itertools::Itertools;
+
+    let until = event.when.signed_duration_since(ping_when);
+
+    let link = format!("https://discordapp.com/channels/{}/{}/{}",
+                       event.guild_id, event.channel_id, event.message_id);
+
+    let chan = ChannelId::from(event.channel_id as u64);
+
+    // keep each message under the length limit
+    for chunk in &attendees.iter().chunks(50) {
+        let content = chunk
+            .fold(MessageBuilder::new(), |m, &u_id| m.user(u_id as u64).push(" "))
+            .push_line("")
+            .push("The event ")
+            .push_bold_safe(&event.title);
+
+        let content = if until > Duration::zero() {
+            content.push(" starts in ").push(human_timedelta(&until))
+        } else {
+            content.push(" is starting now")
+        };
+
+        void!(chan.say(content.push("\n").push(&link)));
+    }
+}
+
+
+fn send_announcement(ann: &Announcement) {
+    use commands::announcements::{split_embed, without_pings};
+
+    let chan = ChannelId::from(ann.channel_id as u64);
+    let text = without_pings(&ann.text);
+
+    if ann.is_embed {
+        let (title, description) = split_embed(&text);
+
+        void!(chan.send_message(|m| m.embed(|e| {
+            let e = e.colour(0x3fb950).description(description);
+
+            match title {
+                Some(title) => e.title(title),
+                None => e,
+            }
+        })));
+    } else {
+        void!(chan.say(&text));
     }
 }
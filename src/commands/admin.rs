@@ -22,6 +22,7 @@ use std::{
 use diesel::prelude::*;
 use ::PgConnectionManager;
 use utils::say;
+use commands::register;
 use itertools::Itertools;
 
 
@@ -278,9 +279,9 @@ command!(guild_info_cmd(_ctx, msg, args) {
     void!(say(msg.channel_id, message));
 });
 
-pub fn setup_admin(_client: &mut Client, frame: StandardFramework) -> StandardFramework {
+pub fn setup_admin(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     frame.group("Admin",
-                |g| g
+                register(client, |g| g
                 .owners_only(true)
                 .help_available(false)
                 .command(
@@ -339,5 +340,5 @@ pub fn setup_admin(_client: &mut Client, frame: StandardFramework) -> StandardFr
                         .cmd(guild_info_cmd)
                         .desc("Get info on a guild.")
                 )
-    )
+    ))
 }
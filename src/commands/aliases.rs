@@ -4,22 +4,310 @@ use serenity::{
         StandardFramework,
         CommandError,
     },
+    model::permissions::Permissions,
     utils::MessageBuilder,
 };
 
 use diesel;
 use diesel::prelude::*;
-use ::PgConnectionManager;
+use ::{PendingAliasImports, PgConnectionManager};
+use base64;
+use itertools::Itertools;
+use serde_json::{self, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    time::{Duration, Instant},
+};
+use commands::{command_bucket, get_command, is_command, register};
 use utils::{
     say,
     pagination::{
         PaginationResult,
         Paginate,
+        parse_list_args,
     },
 };
 
 
-pub fn get_alias(ctx: &Context, name: &str, u_id: i64) -> Option<String> {
+/// Split the arguments given to an alias on whitespace, quoted arguments can contain spaces
+fn split_alias_args(text: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut rest = text.trim_start();
+
+    while !rest.is_empty() {
+        let (arg, after) = if rest.starts_with('"') {
+            match rest[1..].find('"') {
+                Some(end) => (&rest[1..=end], &rest[end + 2..]),
+                None => (&rest[1..], ""),
+            }
+        } else {
+            match rest.find(char::is_whitespace) {
+                Some(end) => (&rest[..end], &rest[end..]),
+                None => (rest, ""),
+            }
+        };
+
+        args.push(arg);
+        rest = after.trim_start();
+    }
+
+    args
+}
+
+
+/// Substitute the arguments given to an alias into its value.
+///
+/// `$1`, `$2`, ... are replaced with single arguments, `$@` with all of them, `${1:default}`
+/// gives a default for a missing argument and `$$` is a literal `$`. Aliases without any of
+/// these have the arguments appended instead.
+pub fn substitute_alias_args(value: &str, input: &str) -> String {
+    let (mut output, substituted) = substitute_args(value, input);
+
+    if !substituted && !input.trim().is_empty() {
+        output.push(' ');
+        output.push_str(input.trim());
+    }
+
+    output
+}
+
+
+/// Substitute the arguments given to an alias, returns if there were any parameters
+fn substitute_args(value: &str, input: &str) -> (String, bool) {
+    let input = input.trim();
+    let args = split_alias_args(input);
+
+    let get = |name: &str| -> Option<&str> {
+        if name == "@" {
+            return Some(input).filter(|s| !s.is_empty());
+        }
+
+        let n = name.parse::<usize>().ok().filter(|n| *n > 0)?;
+        args.get(n - 1).cloned()
+    };
+
+    let mut output = String::new();
+    let mut rest = value;
+    let mut substituted = false;
+
+    while let Some(start) = rest.find('$') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start + 1..];
+
+        if rest.starts_with('$') {
+            output.push('$');
+            rest = &rest[1..];
+        } else if rest.starts_with('@') {
+            output.push_str(get("@").unwrap_or(""));
+            rest = &rest[1..];
+            substituted = true;
+        } else if rest.starts_with('{') && rest.contains('}') {
+            let end = rest.find('}').unwrap();
+            let expr = &rest[1..end];
+
+            let (name, default) = match expr.find(':') {
+                Some(i) => (&expr[..i], &expr[i + 1..]),
+                None => (expr, ""),
+            };
+
+            if name == "@" || name.parse::<usize>().map_or(false, |n| n > 0) {
+                output.push_str(get(name).unwrap_or(default));
+                substituted = true;
+            } else {
+                // not a parameter, leave it as it is
+                output.push('$');
+                output.push_str(&rest[..=end]);
+            }
+
+            rest = &rest[end + 1..];
+        } else {
+            let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or_else(|| rest.len());
+            let name = &rest[..digits];
+
+            if name.parse::<usize>().map_or(false, |n| n > 0) {
+                output.push_str(get(name).unwrap_or(""));
+                rest = &rest[digits..];
+                substituted = true;
+            } else {
+                output.push('$');
+            }
+        }
+    }
+
+    output.push_str(rest);
+
+    (output, substituted)
+}
+
+
+/// Split an alias into the commands it runs, on `;` and newlines. `\;` is a literal `;`.
+fn split_alias_commands(value: &str) -> Vec<String> {
+    let mut commands = vec![String::new()];
+    let mut chars = value.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' if chars.peek() == Some(&';') => {
+                commands.last_mut().unwrap().push(';');
+                chars.next();
+            },
+            ';' | '\n' => commands.push(String::new()),
+            c => commands.last_mut().unwrap().push(c),
+        }
+    }
+
+    commands
+        .into_iter()
+        .map(|c| c.trim().to_owned())
+        .filter(|c| !c.is_empty())
+        .collect()
+}
+
+
+/// Aliases may call other aliases this many levels deep
+const MAX_ALIAS_DEPTH: usize = 5;
+
+/// The most commands and alias calls running an alias may take
+const MAX_ALIAS_STEPS: usize = 20;
+
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum AliasError {
+    #[fail(display = "That alias would call itself: {}", _0)]
+    Cycle(String),
+    #[fail(display = "Aliases can only call other aliases 5 levels deep.")]
+    TooDeep,
+    #[fail(display = "Aliases can run at most 20 commands and aliases.")]
+    TooManySteps,
+}
+
+
+/// The state of expanding an alias into the commands it runs
+struct AliasExpansion<F> {
+    lookup: F,
+    chain: Vec<String>,
+    commands: Vec<String>,
+    steps: usize,
+}
+
+
+impl<F: FnMut(&str) -> Option<String>> AliasExpansion<F> {
+    fn expand(&mut self, value: &str, input: &str) -> Result<(), AliasError> {
+        if self.chain.len() > MAX_ALIAS_DEPTH {
+            return Err(AliasError::TooDeep);
+        }
+
+        let parts = split_alias_commands(value);
+        let single = parts.len() == 1;
+
+        for part in &parts {
+            // arguments are only appended to aliases of one command
+            let command = if single {
+                substitute_alias_args(part, input)
+            } else {
+                substitute_args(part, input).0
+            };
+
+            self.steps += 1;
+
+            if self.steps > MAX_ALIAS_STEPS {
+                return Err(AliasError::TooManySteps);
+            }
+
+            let (name, rest) = match command.find(char::is_whitespace) {
+                Some(i) => (&command[..i], &command[i..]),
+                None => (command.as_str(), ""),
+            };
+
+            match (self.lookup)(name) {
+                Some(inner) => {
+                    let is_cycle = self.chain.iter().any(|c| c == name);
+                    self.chain.push(name.to_owned());
+
+                    if is_cycle {
+                        return Err(AliasError::Cycle(self.chain.join(" -> ")));
+                    }
+
+                    self.expand(&inner, rest)?;
+                    self.chain.pop();
+                },
+                None => self.commands.push(command.clone()),
+            }
+        }
+
+        Ok(())
+    }
+}
+
+
+/// Expand an alias into the commands it runs, following calls to other aliases.
+///
+/// `lookup` gives the value of an alias by name.
+pub fn expand_alias<F>(name: &str, input: &str, mut lookup: F) -> Result<Vec<String>, AliasError>
+    where F: FnMut(&str) -> Option<String>
+{
+    let value = match lookup(name) {
+        Some(v) => v,
+        None => return Ok(Vec::new()),
+    };
+
+    let mut expansion = AliasExpansion {
+        lookup,
+        chain: vec![name.to_owned()],
+        commands: Vec::new(),
+        steps: 0,
+    };
+
+    expansion.expand(&value, input)?;
+
+    Ok(expansion.commands)
+}
+
+
+/// Split a message into the prefix it used and the text following the command name
+pub fn split_command<'a>(content: &'a str, cmd_name: &str) -> (&'a str, &'a str) {
+    content
+        .match_indices(cmd_name)
+        .map(|(i, _)| (&content[..i], &content[i + cmd_name.len()..]))
+        .find(|&(_, rest)| rest.is_empty() || rest.starts_with(char::is_whitespace))
+        .unwrap_or(("", ""))
+}
+
+
+/// Check that each command an alias expands to is registered and can be run by an alias,
+/// rate limited commands can only be used once so an alias can't get around their limit
+pub fn check_alias_commands(ctx: &Context, commands: &[String]) -> Result<(), String> {
+    let mut buckets = HashSet::new();
+
+    for command in commands {
+        let name = command.split_whitespace().next().unwrap_or("");
+
+        if get_command(&ctx, name).is_none() {
+            return Err(if is_command(&ctx, name) {
+                format!("`{}` can't be run by an alias.", name)
+            } else {
+                format!("`{}` is not a command.", name)
+            });
+        }
+
+        if let Some(bucket) = command_bucket(&ctx, name) {
+            if !buckets.insert(bucket) {
+                return Err(format!("`{}` is rate limited, so an alias can only use it (or commands sharing its limit) once.", name));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+
+/// Get the value of an alias, personal aliases take precedence over guild aliases
+pub fn get_alias(ctx: &Context, name: &str, u_id: i64, g_id: Option<i64>) -> Option<String> {
+    get_personal_alias(&ctx, name, u_id).or_else(|| g_id.and_then(|g_id| get_guild_alias(&ctx, name, g_id)))
+}
+
+
+fn get_personal_alias(ctx: &Context, name: &str, u_id: i64) -> Option<String> {
     use schema::command_alias::dsl::*;
 
     let pool = extract_pool!(&ctx);
@@ -32,6 +320,19 @@ pub fn get_alias(ctx: &Context, name: &str, u_id: i64) -> Option<String> {
 }
 
 
+fn get_guild_alias(ctx: &Context, name: &str, g_id: i64) -> Option<String> {
+    use schema::guild_command_alias::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    guild_command_alias
+        .filter(guild_id.eq(g_id))
+        .filter(alias_name.eq(name))
+        .select(alias_value)
+        .first(pool).ok()
+}
+
+
 fn insert_alias(ctx: &Context, u_id: i64, name: &str, alias: &str) {
     use schema::command_alias::dsl::*;
     use models::NewCommandAlias;
@@ -54,6 +355,29 @@ fn insert_alias(ctx: &Context, u_id: i64, name: &str, alias: &str) {
 }
 
 
+fn insert_guild_alias(ctx: &Context, g_id: i64, u_id: i64, name: &str, alias: &str) {
+    use schema::guild_command_alias::dsl::*;
+    use models::NewGuildCommandAlias;
+
+    let new_alias = NewGuildCommandAlias {
+        guild_id: g_id,
+        author_id: u_id,
+        alias_name: name,
+        alias_value: alias,
+    };
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::insert_into(guild_command_alias)
+        .values(&new_alias)
+        .on_conflict((guild_id, alias_name))
+        .do_update()
+        .set((alias_value.eq(alias), author_id.eq(u_id)))
+        .execute(pool)
+        .expect("Couldn't save guild alias");
+}
+
+
 fn delete_alias(ctx: &Context, u_id: i64, name: &str) {
     use schema::command_alias::dsl::*;
 
@@ -66,15 +390,21 @@ fn delete_alias(ctx: &Context, u_id: i64, name: &str) {
         .unwrap();
 }
 
-fn alias_exists(ctx: &Context, u_id: i64, name: &str) -> bool {
-    use schema::command_alias::dsl::*;
+
+fn delete_guild_alias(ctx: &Context, g_id: i64, name: &str) {
+    use schema::guild_command_alias::dsl::*;
 
     let pool = extract_pool!(&ctx);
 
-    diesel::select(diesel::dsl::exists(command_alias
-        .filter(owner_id.eq(u_id))
-        .filter(alias_name.eq(name))))
-        .get_result(pool).expect("Failed to get alias existence")
+    diesel::delete(guild_command_alias
+                   .filter(alias_name.eq(name))
+                   .filter(guild_id.eq(g_id)))
+        .execute(pool)
+        .unwrap();
+}
+
+fn alias_exists(ctx: &Context, u_id: i64, name: &str) -> bool {
+    get_personal_alias(&ctx, name, u_id).is_some()
 }
 
 fn list_aliases(ctx: &Context, u_id: i64, page: i64) -> PaginationResult<(String, String)> {
@@ -91,27 +421,176 @@ fn list_aliases(ctx: &Context, u_id: i64, page: i64) -> PaginationResult<(String
         .unwrap()
 }
 
+fn list_guild_aliases(ctx: &Context, g_id: i64, page: i64) -> PaginationResult<(String, String)> {
+    use schema::guild_command_alias::dsl::*;
 
-command!(list_aliases_cmd(ctx, msg, args) {
-    let page = args.single::<i64>().unwrap_or(1);
+    let pool = extract_pool!(&ctx);
+
+    guild_command_alias
+        .filter(guild_id.eq(g_id))
+        .order(alias_name)
+        .select((alias_name, alias_value))
+        .paginate(page)
+        .load_and_count_pages(pool)
+        .unwrap()
+}
+
+
+/// Check an alias can be run before saving it, `lookup` gives the value of other aliases
+fn check_new_alias<F>(ctx: &Context, name: &str, value: &str, mut lookup: F) -> Result<(), CommandError>
+    where F: FnMut(&str) -> Option<String>
+{
+    if is_command(&ctx, name) {
+        return Err(format!("`{}` is already the name of a command.", name).into());
+    }
 
-    if page <= 0 {
-        return Err("That page does not exist.".into());
+    if split_alias_commands(value).is_empty() {
+        return Err("Aliases need a command to run.".into());
     }
 
-    let aliases = list_aliases(&ctx, msg.author.id.0 as i64, page);
+    // expanded without any arguments
+    let commands = expand_alias(name, "", |n| if n == name { Some(value.to_owned()) } else { lookup(n) })?;
+    check_alias_commands(&ctx, &commands)?;
+
+    Ok(())
+}
+
+
+/// Share codes longer than this would not fit in a message
+const MAX_SHARE_CODE: usize = 1900;
+
+/// How long an alias waits for `alias_import confirm`
+const PENDING_IMPORT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// The most aliases that can be imported from one file
+const MAX_IMPORTED_ALIASES: usize = 200;
+
+
+/// An alias waiting for the user importing it to confirm
+pub struct PendingAliasImport {
+    name: String,
+    value: String,
+    created: Instant,
+}
+
+
+fn alias_json(name: &str, value: &str) -> Value {
+    json!({ "name": name, "value": value })
+}
+
+
+/// Read an alias from its JSON form, checking the name can be used
+fn parse_alias_json(alias: &Value) -> Result<(String, String), &'static str> {
+    let name = alias.get("name").and_then(Value::as_str).ok_or("The alias has no name.")?;
+    let value = alias.get("value").and_then(Value::as_str).ok_or("The alias has no value.")?;
+
+    if name.is_empty() || name.len() > 100 || name.contains(char::is_whitespace) {
+        return Err("Alias names must be a single word of at most 100 characters.");
+    }
+
+    if value.len() > 2000 {
+        return Err("Alias values cannot be longer than 2000 characters.");
+    }
+
+    Ok((name.to_owned(), value.to_owned()))
+}
+
+
+/// Encode an alias as a code that can be pasted into `alias_import`
+fn encode_share_code(name: &str, value: &str) -> String {
+    base64::encode_config(&alias_json(name, value).to_string(), base64::URL_SAFE_NO_PAD)
+}
+
+
+/// Decode an alias from a share code or its JSON form
+fn decode_share_code(code: &str) -> Result<(String, String), &'static str> {
+    const INVALID: &str = "That is not a valid alias code.";
+
+    let code = code.trim().trim_matches('`').trim();
 
-    if !aliases.page_exists() {
-        return Err("That page does not exist or no reminders for this user.".into());
+    let json = if code.starts_with('{') {
+        code.to_owned()
+    } else {
+        let bytes = base64::decode_config(code, base64::URL_SAFE_NO_PAD).map_err(|_| INVALID)?;
+        String::from_utf8(bytes).map_err(|_| INVALID)?
+    };
+
+    let parsed: Value = serde_json::from_str(&json).map_err(|_| INVALID)?;
+
+    parse_alias_json(&parsed)
+}
+
+
+fn write_aliases_export(aliases: &[(String, String)]) -> String {
+    let aliases: Vec<_> = aliases.iter().map(|&(ref n, ref v)| alias_json(n, v)).collect();
+
+    serde_json::to_string_pretty(&json!({ "version": 1, "aliases": aliases })).unwrap()
+}
+
+
+fn parse_aliases_export(json: &str) -> Result<Vec<(String, String)>, &'static str> {
+    let parsed: Value = serde_json::from_str(json).map_err(|_| "That file is not valid JSON.")?;
+
+    let aliases = parsed
+        .get("aliases")
+        .and_then(Value::as_array)
+        .ok_or("That file is not an alias export.")?;
+
+    if aliases.len() > MAX_IMPORTED_ALIASES {
+        return Err("Alias exports can have at most 200 aliases.");
     }
 
-    let block = aliases.block(|(ref w, ref t), i| format!("{:3} | {:<10} | {}", i, w, t));
+    aliases.iter().map(parse_alias_json).collect()
+}
+
+
+fn all_aliases(ctx: &Context, u_id: i64) -> Vec<(String, String)> {
+    use schema::command_alias::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    command_alias
+        .filter(owner_id.eq(u_id))
+        .order(alias_name)
+        .select((alias_name, alias_value))
+        .load(pool)
+        .unwrap()
+}
+
+
+command!(list_aliases_cmd(ctx, msg, args) {
+    let (list, page) = parse_list_args(args.full(), &["mine", "server"])?;
+
+    let mut message = MessageBuilder::new();
+
+    if list == "mine" {
+        let personal = list_aliases(&ctx, msg.author.id.0 as i64, page);
+
+        if !personal.page_exists() {
+            return Err("That page does not exist or you have no aliases.".into());
+        }
+
+        let block = personal.block(|(ref w, ref t), i| format!("{:3} | {:<10} | {}", i, w, t));
+
+        message = message
+            .push("Aliases for ")
+            .mention(&msg.author)
+            .push_line(" (use `alias_list server` for this server's aliases):")
+            .push(block);
+    } else {
+        let g_id = msg.guild_id.ok_or("Server aliases can only be listed in a server.")?;
+        let guild = list_guild_aliases(&ctx, g_id.0 as i64, page);
+
+        if !guild.page_exists() {
+            return Err("That page does not exist or this server has no aliases.".into());
+        }
 
-    let message = MessageBuilder::new()
-        .push("Reminders for ")
-        .mention(&msg.author)
-        .push_line(": ")
-        .push(block);
+        let block = guild.block(|(ref w, ref t), i| format!("{:3} | {:<10} | {}", i, w, t));
+
+        message = message
+            .push_line("Aliases for this server:")
+            .push(block);
+    }
 
     void!(say(msg.channel_id, message));
 });
@@ -121,6 +600,9 @@ command!(add_alias_cmd(ctx, msg, args) {
     let alias_value = args.rest().trim();
 
     let u_id = msg.author.id.0 as i64;
+    let g_id = msg.guild_id.map(|g| g.0 as i64);
+
+    check_new_alias(&ctx, &alias_name, alias_value, |name| get_alias(&ctx, name, u_id, g_id))?;
 
     let exists_already = alias_exists(&ctx, u_id, &alias_name);
 
@@ -148,14 +630,201 @@ command!(delete_alias_cmd(ctx, msg, args) {
 });
 
 
-pub fn setup_aliases(_client: &mut Client, frame: StandardFramework) -> StandardFramework {
+command!(add_guild_alias_cmd(ctx, msg, args) {
+    let alias_name = get_arg!(args, single, String, alias_name);
+    let alias_value = args.rest().trim();
+
+    let g_id = msg.guild_id.unwrap().0 as i64;
+
+    // personal aliases vary between members, so guild aliases can only call guild aliases
+    check_new_alias(&ctx, &alias_name, alias_value, |name| get_guild_alias(&ctx, name, g_id))?;
+
+    let exists_already = get_guild_alias(&ctx, &alias_name, g_id).is_some();
+
+    insert_guild_alias(&ctx, g_id, msg.author.id.0 as i64, &alias_name, &alias_value);
+
+    let response_msg = if exists_already { "Overwrote existing server alias" } else { "Inserted new server alias" };
+
+    void!(say(msg.channel_id, response_msg));
+});
+
+
+command!(delete_guild_alias_cmd(ctx, msg, args) {
+    let alias_name = args.full();
+
+    let g_id = msg.guild_id.unwrap().0 as i64;
+
+    if get_guild_alias(&ctx, &alias_name, g_id).is_none() {
+        void!(say(msg.channel_id, "No server alias with that name exists!"));
+    } else {
+        delete_guild_alias(&ctx, g_id, &alias_name);
+        void!(say(msg.channel_id, "Deleted that server alias!"));
+    }
+});
+
+
+command!(alias_share_cmd(ctx, msg, args) {
+    let alias_name = get_arg!(args, single, String, alias_name);
+
+    let value = get_alias(&ctx, &alias_name, msg.author.id.0 as i64, msg.guild_id.map(|g| g.0 as i64))
+        .ok_or("No alias with that name exists!")?;
+
+    let code = encode_share_code(&alias_name, &value);
+
+    if code.len() > MAX_SHARE_CODE {
+        return Err("That alias is too long to share as a code, use aliases_export instead.".into());
+    }
+
+    let content = MessageBuilder::new()
+        .push_line(format!("Share code for the alias {}, import it with alias_import:", alias_name))
+        .push_codeblock(code, None);
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(alias_import_cmd(ctx, msg, args) {
+    let u_id = msg.author.id.0 as i64;
+    let code = args.full().trim();
+
+    if code.eq_ignore_ascii_case("confirm") || code.eq_ignore_ascii_case("cancel") {
+        let pending = {
+            let mut data = ctx.data.lock();
+            data.get_mut::<PendingAliasImports>().unwrap().remove(&msg.author.id)
+        };
+
+        let pending = pending
+            .filter(|p| p.created.elapsed() < PENDING_IMPORT_TIMEOUT)
+            .ok_or("You have no alias waiting to be imported.")?;
+
+        if code.eq_ignore_ascii_case("cancel") {
+            void!(say(msg.channel_id, "Cancelled importing the alias."));
+            return Ok(());
+        }
+
+        // other aliases may have changed since the preview
+        check_new_alias(&ctx, &pending.name, &pending.value,
+                        |name| get_alias(&ctx, name, u_id, msg.guild_id.map(|g| g.0 as i64)))?;
+
+        insert_alias(&ctx, u_id, &pending.name, &pending.value);
+
+        void!(say(msg.channel_id, format!("Imported the alias {}!", pending.name)));
+        return Ok(());
+    }
+
+    if code.is_empty() {
+        return Err("Give an alias code made by alias_share to import.".into());
+    }
+
+    let (name, value) = decode_share_code(code)?;
+
+    check_new_alias(&ctx, &name, &value, |n| get_alias(&ctx, n, u_id, msg.guild_id.map(|g| g.0 as i64)))?;
+
+    let replaces = if alias_exists(&ctx, u_id, &name) {
+        "This replaces your alias with the same name. "
+    } else {
+        ""
+    };
+
+    let content = MessageBuilder::new()
+        .push_line(format!("The alias {} runs:", name))
+        .push_codeblock_safe(&value, None)
+        .push(format!("{}Use `alias_import confirm` within 5 minutes to add it.", replaces));
+
+    {
+        let mut data = ctx.data.lock();
+        data.get_mut::<PendingAliasImports>().unwrap().insert(msg.author.id, PendingAliasImport {
+            name,
+            value,
+            created: Instant::now(),
+        });
+    }
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(aliases_export_cmd(ctx, msg) {
+    let aliases = all_aliases(&ctx, msg.author.id.0 as i64);
+
+    if aliases.is_empty() {
+        return Err("You have no aliases to export.".into());
+    }
+
+    let export = write_aliases_export(&aliases);
+
+    msg.channel_id.send_files(vec![(export.as_bytes(), "aliases.json")],
+                              |m| m.content(format!("Exported {} aliases.", aliases.len())))?;
+});
+
+
+command!(aliases_import_cmd(ctx, msg) {
+    const MAX_SIZE: u64 = 1_000_000;
+
+    let attachment = msg.attachments
+        .iter()
+        .find(|a| a.filename.to_lowercase().ends_with(".json"))
+        .ok_or("Attach a .json file made by aliases_export to import.")?;
+
+    if attachment.size > MAX_SIZE {
+        return Err("That export is too large.".into());
+    }
+
+    let content = String::from_utf8(attachment.download()?)?;
+    let aliases = parse_aliases_export(&content)?;
+
+    let u_id = msg.author.id.0 as i64;
+    let g_id = msg.guild_id.map(|g| g.0 as i64);
+
+    // aliases in the file can call each other
+    let imported: HashMap<_, _> = aliases.iter().cloned().collect();
+
+    let mut skipped = Vec::new();
+    let mut count = 0;
+
+    for &(ref name, ref value) in &aliases {
+        let checked = check_new_alias(&ctx, name, value, |n| imported
+                                      .get(n)
+                                      .cloned()
+                                      .or_else(|| get_alias(&ctx, n, u_id, g_id)));
+
+        match checked {
+            Ok(()) => {
+                insert_alias(&ctx, u_id, name, value);
+                count += 1;
+            },
+            Err(e) => skipped.push(format!("{}: {}", name, e.0)),
+        }
+    }
+
+    let mut content = MessageBuilder::new()
+        .push(format!("Imported {} aliases", count));
+
+    if skipped.is_empty() {
+        content = content.push(".");
+    } else {
+        content = content
+            .push_line(format!(", skipped {}:", skipped.len()))
+            .push_codeblock_safe(skipped.iter().take(10).join("\n"), None);
+    }
+
+    void!(say(msg.channel_id, content));
+});
+
+
+pub fn setup_aliases(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     frame.group("Aliases",
-                |g| g
+                register(client, |g| g
                 .command(
                     "add_alias", |c| c
                         .cmd(add_alias_cmd)
-                        .desc("Create or overwrite an alias for a command, usable only by you.")
-                        .example("\"something\" remind 3m tea is ready")
+                        .desc(concat!(
+                            "Create or overwrite an alias for a command, usable only by you. Aliases cannot use the name of a command. ",
+                            "Use $1, $2, ... for the arguments given to the alias, $@ for all of them ",
+                            "and ${1:default} to give a default, otherwise the arguments are added to the end. ",
+                            "Separate commands with `;` or new lines to run several, aliases can run other aliases. ",
+                            "Rate limited commands can only be used once in an alias."))
+                        .example("tea remind ${1:3m} tea is ready")
                         .usage("{alias name} {alias value}")
                         .batch_known_as(&["alias_add"])
                 )
@@ -167,11 +836,131 @@ pub fn setup_aliases(_client: &mut Client, frame: StandardFramework) -> Standard
                         .usage("{alias name}")
                         .batch_known_as(&["alias_delete"])
                 )
+                .command(
+                    "add_guild_alias", |c| c
+                        .guild_only(true)
+                        .cmd(add_guild_alias_cmd)
+                        .desc(concat!(
+                            "Create or overwrite an alias that everyone in this server can use, ",
+                            "personal aliases with the same name are used first. ",
+                            "Server aliases can call other server aliases but not personal ones."))
+                        .example("tea remind ${1:3m} tea is ready")
+                        .usage("{alias name} {alias value}")
+                        .batch_known_as(&["guild_alias_add", "add_server_alias"])
+                        .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command(
+                    "delete_guild_alias", |c| c
+                        .guild_only(true)
+                        .cmd(delete_guild_alias_cmd)
+                        .desc("Deletes an alias from this server.")
+                        .example("\"something\"")
+                        .usage("{alias name}")
+                        .batch_known_as(&["guild_alias_delete", "delete_server_alias"])
+                        .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command(
+                    "alias_share", |c| c
+                        .cmd(alias_share_cmd)
+                        .desc("Get a code for one of your aliases that others can import with alias_import.")
+                        .example("tea")
+                        .usage("{alias name}")
+                )
+                .command(
+                    "alias_import", |c| c
+                        .cmd(alias_import_cmd)
+                        .desc(concat!(
+                            "Preview an alias from a code made by alias_share, or its JSON, ",
+                            "then use `alias_import confirm` to add it or `alias_import cancel` to discard it."))
+                        .usage("{code|confirm|cancel}")
+                )
+                .command(
+                    "aliases_export", |c| c
+                        .cmd(aliases_export_cmd)
+                        .desc("Export your aliases as a JSON file.")
+                )
+                .command(
+                    "aliases_import", |c| c
+                        .cmd(aliases_import_cmd)
+                        .desc(concat!(
+                            "Import aliases from an attached file made by aliases_export, ",
+                            "replacing your aliases with the same names."))
+                )
                 .command(
                     "list_aliases", |c| c
                         .cmd(list_aliases_cmd)
-                        .desc("List your aliases")
+                        .desc("List your aliases, or with `server` the aliases of this server")
+                        .example("server 2")
+                        .usage("{mine|server} {page}")
                         .batch_known_as(&["list_alias", "alias_list", "aliases_list"])
                 )
-    )
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_substitute_alias_args() {
+        let cases = &[
+            ("remind $1 tea is ready", "5m", "remind 5m tea is ready"),
+            ("remind ${1:3m} tea is ready", "", "remind 3m tea is ready"),
+            ("remind ${1:3m} tea is ready", "1h", "remind 1h tea is ready"),
+            ("q $2 $1", "a \"b c\"", "q b c a"),
+            ("say $@!", "hello  there", "say hello  there!"),
+            ("say $3.", "a b", "say ."),
+            ("remind 5m", "tea", "remind 5m tea"),
+            ("price $$5 and $0 ${x}", "", "price $5 and $0 ${x}"),
+            ("rate ${@:nothing}", "", "rate nothing"),
+        ];
+
+        for &(value, input, expected) in cases {
+            assert_eq!(substitute_alias_args(value, input), expected, "alias: {} with: {}", value, input);
+        }
+    }
+
+    #[test]
+    fn test_expand_alias() {
+        let aliases = |name: &str| match name {
+            "tea" => Some("remind ${1:3m} tea is ready".to_owned()),
+            "morning" => Some("tea 5m; q\nrate the day \\; $1".to_owned()),
+            "loop" => Some("q; again".to_owned()),
+            "again" => Some("loop".to_owned()),
+            "many" => Some("q;q;q;q;q;q;q;q;q;q;q;q;q;q;q;q;q;q;q;q;q".to_owned()),
+            _ => None,
+        };
+
+        assert_eq!(expand_alias("tea", "", aliases), Ok(vec!["remind 3m tea is ready".to_owned()]));
+        assert_eq!(expand_alias("morning", "great", aliases),
+                   Ok(vec!["remind 5m tea is ready".to_owned(), "q".to_owned(), "rate the day ; great".to_owned()]));
+        assert_eq!(expand_alias("missing", "", aliases), Ok(vec![]));
+        assert_eq!(expand_alias("loop", "", aliases), Err(AliasError::Cycle("loop -> again -> loop".to_owned())));
+        assert_eq!(expand_alias("many", "", aliases), Err(AliasError::TooManySteps));
+    }
+
+    #[test]
+    fn test_share_codes() {
+        let code = encode_share_code("tea", "remind ${1:3m} tea is ready");
+        assert!(code.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_'));
+
+        let alias = ("tea".to_owned(), "remind ${1:3m} tea is ready".to_owned());
+        assert_eq!(decode_share_code(&code), Ok(alias.clone()));
+        assert_eq!(decode_share_code(&format!("`{}`", code)), Ok(alias.clone()));
+        assert_eq!(decode_share_code(r#"{"name": "tea", "value": "remind ${1:3m} tea is ready"}"#), Ok(alias.clone()));
+
+        assert!(decode_share_code("not a code").is_err());
+        assert!(decode_share_code(r#"{"name": "two words", "value": "q"}"#).is_err());
+
+        let aliases = vec![alias, ("q".to_owned(), "q".to_owned())];
+        assert_eq!(parse_aliases_export(&write_aliases_export(&aliases)), Ok(aliases));
+    }
+
+    #[test]
+    fn test_split_command() {
+        assert_eq!(split_command("#!tea 5m", "tea"), ("#!", " 5m"));
+        assert_eq!(split_command("teatea 5m tea", "tea"), ("tea", " 5m tea"));
+        assert_eq!(split_command("#! tea", "tea"), ("#! ", ""));
+    }
 }
@@ -0,0 +1,393 @@
+use serenity::{
+    prelude::*,
+    framework::standard::{
+        StandardFramework,
+        CommandError,
+    },
+    model::{
+        id::ChannelId,
+        permissions::Permissions,
+    },
+    utils::{
+        with_cache,
+        MessageBuilder,
+    },
+};
+use diesel::prelude::*;
+use diesel;
+use ::PgConnectionManager;
+use models::{Announcement, NewAnnouncement};
+use regex::Regex;
+use chrono::{NaiveDateTime, Utc, Duration};
+use commands::{
+    register,
+    events::parse_lead_time,
+    reminders::{channel_label, human_timedelta},
+};
+use utils::{
+    say,
+    with_pool,
+    normalize,
+    dates::recognise_date,
+    pagination::{
+        PaginationResult,
+        Paginate,
+    },
+};
+
+
+/// Announcements may not repeat more often than this
+const MIN_REPEAT_MINUTES: i32 = 10;
+
+
+/// Split the first whitespace separated word off some text
+pub fn next_word(text: &str) -> (&str, &str) {
+    let text = text.trim_start();
+
+    match text.find(char::is_whitespace) {
+        Some(i) => (&text[..i], &text[i..]),
+        None => (text, ""),
+    }
+}
+
+
+/// Parse the options that come before the time of an announcement: `every {interval}` and `embed`.
+///
+/// Returns the minutes between posts, if the announcement is an embed and the remaining text.
+fn parse_schedule(text: &str) -> Result<(Option<i32>, bool, &str), &'static str> {
+    const WEEKDAYS: &[&str] = &["monday", "tuesday", "wednesday", "thursday", "friday", "saturday", "sunday"];
+
+    let (word, rest) = next_word(text);
+
+    let (repeat, text) = if word.eq_ignore_ascii_case("every") {
+        let (first, after_first) = next_word(rest);
+        let (second, after_second) = next_word(after_first);
+        let first = first.to_lowercase();
+
+        if WEEKDAYS.contains(&first.as_str()) {
+            // the weekday is left to be parsed as the first post
+            (Some(60 * 24 * 7), rest)
+        } else {
+            let named = match first.as_str() {
+                "hour" => Some(60),
+                "day" => Some(60 * 24),
+                "week" => Some(60 * 24 * 7),
+                _ => None,
+            };
+
+            match named {
+                Some(m) => (Some(m), after_first),
+                None => match parse_lead_time(&format!("{} {}", first, second)) {
+                    Some(m) => (Some(m), after_second),
+                    None => (Some(parse_lead_time(&first).ok_or("Could not parse how often to repeat, use values such as `day`, `2h` or `monday`.")?), after_first),
+                },
+            }
+        }
+    } else {
+        (None, text)
+    };
+
+    if let Some(m) = repeat {
+        if m < MIN_REPEAT_MINUTES {
+            return Err("Announcements can repeat at most every 10 minutes.");
+        }
+    }
+
+    let (word, rest) = next_word(text);
+
+    let (is_embed, text) = if word.eq_ignore_ascii_case("embed") {
+        (true, rest)
+    } else {
+        (false, text)
+    };
+
+    Ok((repeat, is_embed, text.trim_start()))
+}
+
+
+/// The next time a repeating announcement should be posted after `now`, skipping missed posts.
+pub fn next_run_after(run: NaiveDateTime, repeat_minutes: i32, now: NaiveDateTime) -> NaiveDateTime {
+    if run > now {
+        return run;
+    }
+
+    let step = i64::from(repeat_minutes);
+    let missed = now.signed_duration_since(run).num_minutes() / step + 1;
+
+    run + Duration::minutes(missed * step)
+}
+
+
+/// Stop user, role, everyone and here mentions from pinging anyone
+pub fn without_pings(text: &str) -> String {
+    normalize(text).replace("<@", "<@\u{200B}")
+}
+
+
+/// Split the text of an embed announcement into an optional title and a description, on the first `|`
+pub fn split_embed(text: &str) -> (Option<&str>, &str) {
+    match text.find('|') {
+        Some(i) => (Some(text[..i].trim()), text[i + 1..].trim()),
+        None => (None, text.trim()),
+    }
+}
+
+
+fn describe_repeat(repeat_minutes: Option<i32>) -> String {
+    match repeat_minutes {
+        Some(m) => format!("every {}", human_timedelta(&Duration::minutes(i64::from(m)))),
+        None => "once".to_owned(),
+    }
+}
+
+
+fn insert_announcement(ctx: &Context, announcement: &NewAnnouncement) {
+    use schema::announcement;
+
+    with_pool(&ctx, |pool| diesel::insert_into(announcement::table)
+              .values(announcement)
+              .execute(&pool)
+              .expect("Could not insert announcement"));
+}
+
+
+fn list_announcements(ctx: &Context, g_id: i64, page: i64)
+                      -> PaginationResult<(NaiveDateTime, i64, Option<i32>, bool, String)> {
+    use schema::announcement::dsl::*;
+
+    with_pool(&ctx, |pool| announcement
+              .filter(guild_id.eq(g_id))
+              .order(id)
+              .select((next_run, channel_id, repeat_minutes, paused, text))
+              .paginate(page)
+              .load_and_count_pages(&pool)
+              .unwrap())
+}
+
+
+fn get_announcement_by_index(ctx: &Context, g_id: i64, idx: i64) -> Option<Announcement> {
+    use schema::announcement::dsl::*;
+
+    // same ordering as `list_announcements`
+    with_pool(&ctx, |pool| announcement
+              .filter(guild_id.eq(g_id))
+              .order(id)
+              .offset(idx - 1)
+              .first(&pool)
+              .ok())
+}
+
+
+fn set_paused(ctx: &Context, a_id: i64, is_paused: bool, run: NaiveDateTime) {
+    use schema::announcement::dsl::*;
+
+    with_pool(&ctx, |pool| diesel::update(announcement.find(a_id))
+              .set((paused.eq(is_paused), next_run.eq(run)))
+              .execute(&pool)
+              .unwrap());
+}
+
+
+fn delete_announcement(ctx: &Context, a_id: i64) {
+    use schema::announcement::dsl::*;
+
+    with_pool(&ctx, |pool| diesel::delete(announcement.find(a_id))
+              .execute(&pool)
+              .unwrap());
+}
+
+
+command!(announce_schedule_cmd(ctx, msg, args) {
+    lazy_static! {
+        static ref CHANNEL_RE: Regex = Regex::new(r"^\s*<#(?P<id>\d+)>").unwrap();
+    }
+
+    let g_id = msg.guild_id.unwrap();
+    let full = args.full();
+
+    let caps = CHANNEL_RE.captures(full).ok_or("Mention the channel to post the announcement in first.")?;
+    let c_id = ChannelId::from((&caps["id"]).parse::<u64>()?);
+    let rest = &full[caps.get(0).unwrap().end()..];
+
+    let in_guild = log_time!(with_cache(
+        |cache| cache.guild_channel(c_id).map_or(false, |c| c.read().guild_id == g_id)
+    ), "with_cache: announcement channel in guild");
+
+    if !in_guild {
+        return Err("That channel is not in this server.".into());
+    }
+
+    let (repeat, is_embed, rest) = parse_schedule(rest)?;
+
+    let now = Utc::now().naive_utc();
+    let (when, text) = recognise_date(now, rest)?;
+
+    if text.is_empty() {
+        return Err("Announcements need some text to post.".into());
+    }
+
+    if is_embed {
+        if let (Some(title), _) = split_embed(&text) {
+            if title.len() > 256 {
+                return Err("Embed titles cannot be longer than 256 characters.".into());
+            }
+        }
+    }
+
+    insert_announcement(&ctx, &NewAnnouncement {
+        guild_id: g_id.0 as i64,
+        channel_id: c_id.0 as i64,
+        author_id: msg.author.id.0 as i64,
+        text: &text,
+        is_embed,
+        next_run: &when,
+        repeat_minutes: repeat,
+    });
+
+    let delta = when.signed_duration_since(now);
+
+    let content = MessageBuilder::new()
+        .push("Okay, I'll post that in ")
+        .channel(c_id)
+        .push(" in ")
+        .push(human_timedelta(&delta));
+
+    let content = match repeat {
+        Some(_) => content.push(", then ").push(describe_repeat(repeat)),
+        None => content,
+    };
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(announce_list_cmd(ctx, msg, args) {
+    let page = args.single::<i64>().unwrap_or(1);
+
+    if page <= 0 {
+        return Err("That page does not exist.".into());
+    }
+
+    let announcements = list_announcements(&ctx, msg.guild_id.unwrap().0 as i64, page);
+
+    if !announcements.page_exists() {
+        return Err("That page does not exist or no announcements are scheduled for this server.".into());
+    }
+
+    let block = announcements.block(|(ref w, ref c, ref r, ref p, ref t), i| {
+        let preview: String = t.chars().take(40).collect();
+        let status = if *p { "paused" } else { "" };
+
+        format!("{:>3} | {} | {} | {} | {:<6} | {}",
+                i, w.format("%Y-%m-%d %H:%M"), channel_label(*c), describe_repeat(*r), status, preview)
+    });
+
+    let message = MessageBuilder::new()
+        .push_line("Scheduled announcements (times are UTC):")
+        .push(block);
+
+    void!(say(msg.channel_id, message));
+});
+
+
+command!(announce_pause_cmd(ctx, msg, args) {
+    let index = get_arg!(args, single, usize, index) as i64;
+
+    let ann = get_announcement_by_index(&ctx, msg.guild_id.unwrap().0 as i64, index)
+        .ok_or("That announcement doesn't exist.")?;
+
+    if ann.paused {
+        // don't post every missed repeat at once when resuming
+        let now = Utc::now().naive_utc();
+        let run = match ann.repeat_minutes {
+            Some(m) => next_run_after(ann.next_run, m, now),
+            None => ann.next_run,
+        };
+
+        set_paused(&ctx, ann.id, false, run);
+        void!(say(msg.channel_id, format!("Resumed the announcement, it will next be posted at {} UTC.",
+                                          run.format("%Y-%m-%d %H:%M"))));
+    } else {
+        set_paused(&ctx, ann.id, true, ann.next_run);
+        void!(say(msg.channel_id, "Paused the announcement, use this command again to resume it."));
+    }
+});
+
+
+command!(announce_delete_cmd(ctx, msg, args) {
+    let index = get_arg!(args, single, usize, index) as i64;
+
+    let ann = get_announcement_by_index(&ctx, msg.guild_id.unwrap().0 as i64, index)
+        .ok_or("That announcement doesn't exist.")?;
+
+    delete_announcement(&ctx, ann.id);
+
+    void!(say(msg.channel_id, "Deleted the announcement."));
+});
+
+
+pub fn setup_announcements(client: &mut Client, frame: StandardFramework) -> StandardFramework {
+    frame.group("Announcements",
+                register(client, |g| g
+                .guild_only(true)
+                .command("announce_schedule", |c| c
+                         .cmd(announce_schedule_cmd)
+                         .desc(concat!(
+                             "Schedule a message to be posted in a channel, once or repeating with `every {interval}`. ",
+                             "Start the text with `embed` to post it as an embed, a title can be given before a `|`. ",
+                             "Times are given in the same way as the `remind` command, mentions in the text will not ping."))
+                         .example("#general every monday at 10:00 The weekly meeting starts now: <link>")
+                         .usage("{channel} [every {interval}] [embed] {when} {text}")
+                         .batch_known_as(&["schedule_announcement"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command("announce_list", |c| c
+                         .cmd(announce_list_cmd)
+                         .desc("List the scheduled announcements in this server.")
+                         .usage("{page}")
+                         .batch_known_as(&["announcements", "list_announcements"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command("announce_pause", |c| c
+                         .cmd(announce_pause_cmd)
+                         .desc("Pause or resume a scheduled announcement by index.")
+                         .usage("{index}")
+                         .batch_known_as(&["announce_resume"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command("announce_delete", |c| c
+                         .cmd(announce_delete_cmd)
+                         .desc("Delete a scheduled announcement by index.")
+                         .usage("{index}")
+                         .batch_known_as(&["delete_announcement"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_schedule() {
+        assert_eq!(parse_schedule("tomorrow hi"), Ok((None, false, "tomorrow hi")));
+        assert_eq!(parse_schedule("every day at 9am hi"), Ok((Some(1440), false, "at 9am hi")));
+        assert_eq!(parse_schedule("every 2 hours embed hi"), Ok((Some(120), true, "hi")));
+        assert_eq!(parse_schedule("every 1w in 2 days hi"), Ok((Some(10080), false, "in 2 days hi")));
+        assert_eq!(parse_schedule("every Monday at 10:00 hi"), Ok((Some(10080), false, "Monday at 10:00 hi")));
+        assert!(parse_schedule("every 5m hi").is_err());
+        assert!(parse_schedule("every so often hi").is_err());
+    }
+
+    #[test]
+    fn test_next_run_after() {
+        let run = NaiveDateTime::parse_from_str("2019-03-06 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+        let now = run + Duration::minutes(150);
+
+        assert_eq!(next_run_after(run, 60, run - Duration::minutes(1)), run);
+        assert_eq!(next_run_after(run, 60, run), run + Duration::minutes(60));
+        assert_eq!(next_run_after(run, 60, now), run + Duration::minutes(180));
+    }
+}
@@ -4,17 +4,31 @@ use serenity::{
     framework::standard::{
         StandardFramework,
     },
+    model::{
+        id::{ChannelId, GuildId},
+        permissions::Permissions,
+    },
+    utils::MessageBuilder,
 };
+use diesel;
+use diesel::prelude::*;
 use serde_json::Value;
 use serde_json;
 use reqwest::header;
 use reqwest;
 use itertools::Itertools;
 use rand::{thread_rng, seq::SliceRandom};
-use typemap::Key;
-use std::marker;
+use typemap::{Key, ShareMap};
+use lru_cache::LruCache;
+use commands::register;
+use std::{
+    collections::HashSet,
+    marker,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use failure::Error;
-use utils::{nsfw_check, send_message, say};
+use utils::{nsfw_check, send_message, say, with_pool};
 
 
 #[derive(Debug, Fail)]
@@ -25,9 +39,15 @@ enum BooruError {
     InvalidResponse,
     #[fail(display = "No images found for tags: `{}`.", _0)]
     NoImages(String),
+    #[fail(display = "The tag `{}` is blacklisted in this server.", _0)]
+    Blacklisted(String),
 }
 
 
+/// Guilds may not blacklist more tags than this
+const MAX_BLACKLIST: i64 = 100;
+
+
 struct BooruClient;
 
 impl Key for BooruClient {
@@ -46,6 +66,102 @@ impl BooruClient {
 }
 
 
+/// How long the results of a search are reused for
+const RESULT_TTL: Duration = Duration::from_secs(10 * 60);
+
+
+/// The posts found by a search, reused by later searches for the same tags
+struct CachedSearch {
+    results: Arc<Vec<Value>>,
+    fetched: Instant,
+}
+
+
+/// Search results by booru and tags
+struct BooruCache;
+
+impl Key for BooruCache {
+    type Value = LruCache<String, CachedSearch>;
+}
+
+
+/// The images already shown in a channel for a search
+struct BooruShown;
+
+impl Key for BooruShown {
+    type Value = LruCache<(ChannelId, String), HashSet<String>>;
+}
+
+
+/// Split the candidate urls into those not yet seen and those already seen, along with their indexes
+fn split_seen<'a>(urls: &[Option<&'a str>], seen: &HashSet<String>) -> (Vec<(usize, &'a str)>, Vec<(usize, &'a str)>) {
+    urls.iter()
+        .enumerate()
+        .filter_map(|(i, u)| u.map(|u| (i, u)))
+        .partition(|&(_, u)| !seen.contains(u))
+}
+
+
+/// Check if a post's space separated tags contain a blacklisted tag
+fn has_blacklisted_tag(tags: &str, blacklist: &[String]) -> bool {
+    tags.split_whitespace().any(|t| blacklist.iter().any(|b| b.eq_ignore_ascii_case(t)))
+}
+
+
+/// Check if a post should be left out, posts without readable tags can't be checked against a blacklist
+fn is_post_blacklisted(tags: Option<&str>, blacklist: &[String]) -> bool {
+    match tags {
+        Some(tags) => has_blacklisted_tag(tags, blacklist),
+        None => !blacklist.is_empty(),
+    }
+}
+
+
+fn get_blacklist(ctx: &Context, g_id: Option<GuildId>) -> Vec<String> {
+    use schema::booru_blacklist::dsl::*;
+
+    let g_id = match g_id {
+        Some(g) => g.0 as i64,
+        None => return Vec::new(),
+    };
+
+    with_pool(&ctx, |pool| booru_blacklist
+              .filter(guild_id.eq(g_id))
+              .order(tag)
+              .select(tag)
+              .load(&pool)
+              .unwrap())
+}
+
+
+fn add_to_blacklist(ctx: &Context, g_id: i64, tags: &[String]) {
+    use schema::booru_blacklist;
+    use models::NewBooruBlacklist;
+
+    let rows: Vec<_> = tags
+        .iter()
+        .map(|t| NewBooruBlacklist { guild_id: g_id, tag: t })
+        .collect();
+
+    with_pool(&ctx, |pool| diesel::insert_into(booru_blacklist::table)
+              .values(&rows)
+              .on_conflict_do_nothing()
+              .execute(&pool)
+              .unwrap());
+}
+
+
+fn remove_from_blacklist(ctx: &Context, g_id: i64, tags: &[String]) -> usize {
+    use schema::booru_blacklist::dsl::*;
+
+    with_pool(&ctx, |pool| diesel::delete(booru_blacklist
+                                          .filter(guild_id.eq(g_id))
+                                          .filter(tag.eq_any(tags)))
+              .execute(&pool)
+              .unwrap())
+}
+
+
 struct BooruResponse<T: ?Sized + BooruRequestor> {
     image_url: String,
     tags: String,
@@ -91,18 +207,53 @@ impl<T: ?Sized + BooruRequestor> BooruResponse<T> {
 #[derive(Clone)]
 struct BooruContext<'a> {
     client: &'a reqwest::Client,
+    data: &'a Arc<Mutex<ShareMap>>,
+    channel_id: ChannelId,
     tags: &'a [String],
+    blacklist: &'a [String],
     params: Option<Vec<(&'static str, String)>>,
 }
 
 impl<'a> BooruContext<'a> {
-    fn new(client: &'a reqwest::Client, tags: &'a [String], params: Option<Vec<(&'static str, String)>>) -> Self {
+    fn new(client: &'a reqwest::Client, data: &'a Arc<Mutex<ShareMap>>, channel_id: ChannelId,
+           tags: &'a [String], blacklist: &'a [String], params: Option<Vec<(&'static str, String)>>) -> Self {
         BooruContext {
             client,
+            data,
+            channel_id,
             tags,
+            blacklist,
             params,
         }
     }
+
+    /// The tags to search for, along with the negated blacklist if the booru supports it
+    fn query_tags(&self, negate_blacklist: bool) -> String {
+        let negated = self.blacklist
+            .iter()
+            .filter(|_| negate_blacklist)
+            .map(|t| format!("-{}", t));
+
+        self.tags.iter().cloned().chain(negated).join(" ")
+    }
+
+    /// Identifies a search on a booru, tags are compared ignoring order and case
+    fn search_key(&self, booru: &str) -> String {
+        let tags = self.tags
+            .iter()
+            .map(|t| t.to_lowercase())
+            .sorted()
+            .dedup()
+            .join(" ");
+
+        let params = self.params
+            .iter()
+            .flatten()
+            .map(|&(k, ref v)| format!("{}={}", k, v))
+            .join("&");
+
+        format!("{}:{}:{}:{}", booru, params, tags, self.blacklist.join(" "))
+    }
 }
 
 
@@ -126,33 +277,66 @@ trait BooruRequestor {
     const PAGE_PATH: &'static str;
     const URL_KEY: &'static str = "file_url";
     const TAG_KEY: &'static str = "tags";
+    /// If blacklisted tags can be negated in the search
+    const NEGATES_TAGS: bool = true;
 
     fn full_url() -> String {
         format!("{}{}", Self::BASE_URL, Self::EXTENSION)
     }
 
     fn params(ctx: &BooruContext) -> Vec<(&'static str, String)> {
-        vec![("tags", ctx.tags.iter().join(" "))]
+        vec![("tags", ctx.query_tags(Self::NEGATES_TAGS))]
     }
 
     fn select_response<'a>(ctx: &BooruContext, resps: &'a [Value],
                            key: &'static str) -> Result<&'a Value, Error>
     {
-        // get all the image urls from each response
-        let mut urls: Vec<_> = resps
+        let shown_key = (ctx.channel_id, ctx.search_key(Self::BOORU_NAME));
+
+        // get all the image urls from each response, leaving out posts with blacklisted tags
+        let urls: Vec<_> = resps
             .iter()
-            .map(|v| v[&key].as_str())
-            .enumerate()
+            .map(|v| if is_post_blacklisted(v[Self::TAG_KEY].as_str(), ctx.blacklist) {
+                None
+            } else {
+                v[&key].as_str()
+            })
             .collect();
 
-        urls.shuffle(&mut thread_rng());
+        let (mut unseen, mut seen) = {
+            let mut data = ctx.data.lock();
+            let shown = data.get_mut::<BooruShown>().unwrap();
+
+            match shown.get_mut(&shown_key) {
+                Some(seen) => split_seen(&urls, seen),
+                None => split_seen(&urls, &HashSet::new()),
+            }
+        };
+
+        unseen.shuffle(&mut thread_rng());
+        seen.shuffle(&mut thread_rng());
+
+        // images already shown in this channel are only used again when none of the others work,
+        // which starts a new round of images
+        let candidates = unseen
+            .into_iter()
+            .map(|c| (c, false))
+            .chain(seen.into_iter().map(|c| (c, true)));
 
         // for each url, check that a HTTP HEAD on the url gives a result
-        for (index, url) in urls {
-            let url = try_opt_continue!(url);
+        for ((index, url), new_round) in candidates {
             let resp = try_continue!(ctx.client.head(url).send());
 
             if resp.status().is_success() {
+                let mut data = ctx.data.lock();
+                let shown = data.get_mut::<BooruShown>().unwrap();
+
+                if new_round || !shown.contains_key(&shown_key) {
+                    shown.insert(shown_key.clone(), HashSet::new());
+                }
+
+                shown.get_mut(&shown_key).unwrap().insert(url.to_owned());
+
                 return Ok(&resps[index]);
             }
         }
@@ -160,6 +344,33 @@ trait BooruRequestor {
         Err(BooruError::NoImages(ctx.tags.iter().join(" ")).into())
     }
 
+    /// Search the booru, reusing recent results for the same search
+    fn cached_search(ctx: &BooruContext) -> Result<Arc<Vec<Value>>, Error> {
+        let key = ctx.search_key(Self::BOORU_NAME);
+
+        {
+            let mut data = ctx.data.lock();
+            let cache = data.get_mut::<BooruCache>().unwrap();
+
+            if let Some(cached) = cache.get_mut(&key) {
+                if cached.fetched.elapsed() < RESULT_TTL {
+                    return Ok(cached.results.clone());
+                }
+            }
+        }
+
+        let resp = Self::search_for(ctx)?;
+        let results = Arc::new(Self::parse_response(&resp)?);
+
+        let mut data = ctx.data.lock();
+        data.get_mut::<BooruCache>().unwrap().insert(key, CachedSearch {
+            results: results.clone(),
+            fetched: Instant::now(),
+        });
+
+        Ok(results)
+    }
+
     fn parse_response(val: &str) -> Result<Vec<Value>, Error> {
         serde_json::from_str(val).map_err(|_| BooruError::InvalidResponse.into())
     }
@@ -171,8 +382,11 @@ trait BooruRequestor {
     }
 
     fn search(ctx: &BooruContext) -> Result<BooruResponse<Self>, Error> {
-        let resp = Self::search_for(ctx)?;
-        let parsed = Self::parse_response(&resp)?;
+        if let Some(t) = ctx.tags.iter().find(|t| has_blacklisted_tag(t, ctx.blacklist)) {
+            return Err(BooruError::Blacklisted(t.to_owned()).into());
+        }
+
+        let parsed = Self::cached_search(ctx)?;
         let selected = Self::select_response(ctx, &parsed, Self::URL_KEY)?;
 
         Ok(BooruResponse::new(
@@ -208,8 +422,10 @@ impl BooruRequestor for Ninja {
     // we don't use this
     const PAGE_PATH: &'static str = "";
 
+    const NEGATES_TAGS: bool = false;
+
     fn params(ctx: &BooruContext) -> Vec<(&'static str, String)> {
-        vec![("q", ctx.tags.iter().join(" ")), ("o", "r".to_owned())]
+        vec![("q", ctx.query_tags(Self::NEGATES_TAGS)), ("o", "r".to_owned())]
     }
 
     fn parse_response(val: &str) -> Result<Vec<Value>, Error> {
@@ -264,9 +480,10 @@ macro_rules! booru_def {
             };
 
             let tags = args.multiple::<String>().unwrap_or_else(|_| Vec::new());
+            let blacklist = get_blacklist(&ctx, msg.guild_id);
 
-            let ctx = BooruContext::new(&client, &tags, None);
-            let response = <$booru as BooruRequestor>::search(&ctx)?;
+            let b_ctx = BooruContext::new(&client, &ctx.data, msg.channel_id, &tags, &blacklist, None);
+            let response = <$booru as BooruRequestor>::search(&b_ctx)?;
 
             void!(send_message(msg.channel_id, |m| m.embed(|e| response.generate_embed(e))));
         });
@@ -285,8 +502,11 @@ booru_def!(
     page_path: "posts/",
 
     {
+        // negated tags count towards danbooru's two tag limit
+        const NEGATES_TAGS: bool = false;
+
         fn params(ctx: &BooruContext) -> Vec<(&'static str, String)> {
-            vec![("tags", ctx.tags.iter().join(" ")), ("random", "true".to_owned())]
+            vec![("tags", ctx.query_tags(Self::NEGATES_TAGS)), ("random", "true".to_owned())]
         }
     }
 );
@@ -334,7 +554,7 @@ booru_def!(
                  ("s", "post".to_owned()),
                  ("q", "index".to_owned()),
                  ("json", "1".to_owned()),
-                 ("tags", ctx.tags.iter().join(" "))
+                 ("tags", ctx.query_tags(Self::NEGATES_TAGS))
             ]
         }
     }
@@ -357,7 +577,7 @@ booru_def!(
                  ("s", "post".to_owned()),
                  ("q", "index".to_owned()),
                  ("json", "1".to_owned()),
-                 ("tags", ctx.tags.iter().join(" "))
+                 ("tags", ctx.query_tags(Self::NEGATES_TAGS))
             ]
         }
 
@@ -398,12 +618,14 @@ command!(ninja_cmd(ctx, msg, args) {
     };
 
     let tags = args.multiple::<String>().unwrap_or_else(|_| Vec::new());
+    let blacklist = get_blacklist(&ctx, msg.guild_id);
 
     let is_nsfw = msg.channel_id.to_channel_cached().map_or(false, |c| c.is_nsfw());
     let nsfw_key = if is_nsfw { "a" } else { "s" }; // a = any, s = safe
 
-    let ctx = BooruContext::new(&client, &tags, Some(vec![("f", nsfw_key.to_owned())]));
-    let response = Ninja::search(&ctx)?;
+    let b_ctx = BooruContext::new(&client, &ctx.data, msg.channel_id, &tags, &blacklist,
+                                  Some(vec![("f", nsfw_key.to_owned())]));
+    let response = Ninja::search(&b_ctx)?;
 
     void!(send_message(msg.channel_id, |m| m.embed(|e| response.generate_embed(e))));
 });
@@ -419,6 +641,7 @@ command!(booru_bomb(ctx, msg, args) {
     };
 
     let tags = args.multiple::<String>().unwrap_or_else(|_| Vec::new());
+    let blacklist = get_blacklist(&ctx, msg.guild_id);
 
     let channel_id = msg.channel_id;
 
@@ -426,10 +649,11 @@ command!(booru_bomb(ctx, msg, args) {
         ( $booru:ident ) => ( {
             let data = ctx.data.clone();
             let tags = tags.clone();
+            let blacklist = blacklist.clone();
             threadpool.execute(move || {
                 let client = data.lock().get::<BooruClient>().unwrap().clone();
 
-                let b_ctx = BooruContext::new(&client, &tags, None);
+                let b_ctx = BooruContext::new(&client, &data, channel_id, &tags, &blacklist, None);
 
                 let resp = <$booru as BooruRequestor>::search(&b_ctx);
                 match resp {
@@ -447,16 +671,77 @@ command!(booru_bomb(ctx, msg, args) {
 });
 
 
+command!(booru_blacklist_cmd(ctx, msg, args) {
+    let g_id = msg.guild_id.unwrap();
+    let action = get_arg!(args, single, String, action, "list".to_owned()).to_lowercase();
+
+    // tags are stored without a leading `-` so they can be negated in searches
+    let tags: Vec<String> = args.multiple::<String>()
+        .unwrap_or_else(|_| Vec::new())
+        .iter()
+        .map(|t| t.trim_start_matches('-').to_lowercase())
+        .filter(|t| !t.is_empty())
+        .unique()
+        .collect();
+
+    match action.as_str() {
+        "add" => {
+            if tags.is_empty() {
+                return Err("Give the tags to blacklist.".into());
+            }
+
+            if tags.iter().any(|t| t.len() > 200) {
+                return Err("Tags cannot be longer than 200 characters.".into());
+            }
+
+            let current = get_blacklist(&ctx, Some(g_id));
+            let new_count = current.len() + tags.iter().filter(|t| !current.contains(t)).count();
+
+            if new_count as i64 > MAX_BLACKLIST {
+                return Err(format!("Servers can blacklist at most {} tags.", MAX_BLACKLIST).into());
+            }
+
+            add_to_blacklist(&ctx, g_id.0 as i64, &tags);
+            void!(say(msg.channel_id, format!("Blacklisted: {}", tags.join(", "))));
+        },
+        "remove" => {
+            if tags.is_empty() {
+                return Err("Give the tags to remove from the blacklist.".into());
+            }
+
+            let removed = remove_from_blacklist(&ctx, g_id.0 as i64, &tags);
+            void!(say(msg.channel_id, format!("Removed {} tags from the blacklist.", removed)));
+        },
+        "list" => {
+            let blacklist = get_blacklist(&ctx, Some(g_id));
+
+            if blacklist.is_empty() {
+                void!(say(msg.channel_id, "No tags are blacklisted in this server."));
+            } else {
+                let content = MessageBuilder::new()
+                    .push_line("Blacklisted booru tags:")
+                    .push_codeblock_safe(blacklist.join(" "), None);
+
+                void!(say(msg.channel_id, content));
+            }
+        },
+        _ => return Err("The action must be one of: add, remove or list.".into()),
+    }
+});
+
+
 pub fn setup_booru(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     {
         let mut data = client.data.lock();
         data.insert::<BooruClient>(BooruClient::generate());
+        data.insert::<BooruCache>(LruCache::new(500));
+        data.insert::<BooruShown>(LruCache::new(1000));
     }
 
     frame
         .bucket("booru_bucket", 3, 10, 2)
         .group("Booru",
-               |g| g
+               register(client, |g| g
                .bucket("booru_bucket")
                .command("booru", |c| c
                         .cmd(ninja_cmd)
@@ -499,5 +784,54 @@ pub fn setup_booru(client: &mut Client, frame: StandardFramework) -> StandardFra
                         .desc("Search each booru for an image.")
                         .check(nsfw_check)
                )
-    )
+    ))
+        // settings aren't searches, so don't share their rate limit
+        .group("Booru Blacklist",
+               register(client, |g| g
+               .command("booru_blacklist", |c| c
+                        .cmd(booru_blacklist_cmd)
+                        .desc(concat!(
+                            "Add, remove or list tags that are never shown by booru searches in this server, ",
+                            "whatever the booru or channel."))
+                        .example("add gore")
+                        .usage("{add|remove|list} {tags}")
+                        .guild_only(true)
+                        .required_permissions(Permissions::ADMINISTRATOR)
+               )
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_has_blacklisted_tag() {
+        let blacklist = vec!["gore".to_owned(), "spoilers".to_owned()];
+
+        assert!(has_blacklisted_tag("cat_ears Gore smile", &blacklist));
+        assert!(!has_blacklisted_tag("cat_ears gore_(genre) smile", &blacklist));
+        assert!(!has_blacklisted_tag("", &blacklist));
+        assert!(!has_blacklisted_tag("gore", &[]));
+
+        assert!(is_post_blacklisted(None, &blacklist));
+        assert!(!is_post_blacklisted(None, &[]));
+        assert!(!is_post_blacklisted(Some("smile"), &blacklist));
+    }
+
+    #[test]
+    fn test_split_seen() {
+        let urls = &[Some("a"), None, Some("b"), Some("c")];
+        let mut seen = HashSet::new();
+
+        assert_eq!(split_seen(urls, &seen), (vec![(0, "a"), (2, "b"), (3, "c")], vec![]));
+
+        seen.insert("a".to_owned());
+        seen.insert("c".to_owned());
+        assert_eq!(split_seen(urls, &seen), (vec![(2, "b")], vec![(0, "a"), (3, "c")]));
+
+        seen.insert("b".to_owned());
+        assert_eq!(split_seen(urls, &seen), (vec![], vec![(0, "a"), (2, "b"), (3, "c")]));
+    }
 }
@@ -0,0 +1,399 @@
+use serenity::{
+    prelude::*,
+    framework::standard::{
+        StandardFramework,
+        CommandError,
+    },
+    model::{
+        channel::{Reaction, ReactionType},
+        permissions::Permissions,
+    },
+    utils::{
+        with_cache,
+        MessageBuilder,
+    },
+};
+use diesel::prelude::*;
+use diesel;
+use ::PgConnectionManager;
+use models::GuildEvent;
+use regex::Regex;
+use chrono::{NaiveDateTime, Utc, Duration};
+use itertools::Itertools;
+use commands::register;
+use utils::{
+    say,
+    with_pool,
+    dates::recognise_date,
+    pagination::{
+        PaginationResult,
+        Paginate,
+    },
+};
+
+
+/// Members react with this to attend an event
+pub const RSVP_EMOJI: char = '✅';
+
+
+fn get_lead_times(ctx: &Context, g_id: i64) -> Vec<i32> {
+    use schema::guild::dsl::*;
+
+    with_pool(&ctx, |pool| guild
+              .find(g_id)
+              .select(event_lead_times)
+              .first(&pool)
+              .unwrap_or_else(|_| vec![24 * 60, 15]))
+}
+
+
+fn set_lead_times(ctx: &Context, g_id: i64, times: &[i32]) {
+    use schema::guild::dsl::*;
+
+    with_pool(&ctx, |pool| diesel::update(guild.find(g_id))
+              .set(event_lead_times.eq(times))
+              .execute(&pool)
+              .unwrap());
+}
+
+
+/// Parse a lead time such as `15m`, `2h` or `1d` into minutes, plain numbers are minutes.
+pub fn parse_lead_time(s: &str) -> Option<i32> {
+    lazy_static! {
+        static ref LEAD_RE: Regex = Regex::new(r"^(?P<value>\d+)\s*(?P<unit>m|mins?|h|hours?|d|days?|w|weeks?)?$").unwrap();
+    }
+
+    let caps = LEAD_RE.captures(s.trim())?;
+    let value = (&caps["value"]).parse::<i32>().ok()?;
+
+    let multiplier = match caps.name("unit").map(|u| &u.as_str()[..1]) {
+        None | Some("m") => 1,
+        Some("h") => 60,
+        Some("d") => 60 * 24,
+        Some("w") => 60 * 24 * 7,
+        _ => unreachable!(),
+    };
+
+    value.checked_mul(multiplier)
+}
+
+
+/// Parse a comma separated list of lead times, such as `1d, 2 hours, 15m`
+pub fn parse_lead_times(s: &str) -> Option<Vec<i32>> {
+    s.split(',')
+        .map(str::trim)
+        .filter(|t| !t.is_empty())
+        .map(parse_lead_time)
+        .collect()
+}
+
+
+fn insert_event(ctx: &Context, g_id: i64, c_id: i64, m_id: i64, author_id: i64,
+                title: &str, when: NaiveDateTime) {
+    use models::{NewGuildEvent, NewGuildEventPing};
+    use schema::{guild_event, guild_event_ping};
+
+    let now = Utc::now().naive_utc();
+    let lead_times = get_lead_times(&ctx, g_id);
+
+    let event = NewGuildEvent {
+        guild_id: g_id,
+        channel_id: c_id,
+        message_id: m_id,
+        author_id,
+        title,
+        when: &when,
+    };
+
+    let ping_times: Vec<_> = lead_times
+        .iter()
+        .map(|&mins| when - Duration::minutes(i64::from(mins)))
+        .filter(|t| *t > now)
+        .collect();
+
+    with_pool(&ctx, |pool| {
+        let e_id: i64 = diesel::insert_into(guild_event::table)
+            .values(&event)
+            .returning(guild_event::id)
+            .get_result(&pool)
+            .expect("Could not insert event");
+
+        let pings: Vec<_> = ping_times
+            .iter()
+            .map(|t| NewGuildEventPing {
+                event_id: e_id,
+                when: t,
+            })
+            .collect();
+
+        diesel::insert_into(guild_event_ping::table)
+            .values(&pings)
+            .execute(&pool)
+            .expect("Could not insert event pings");
+    });
+}
+
+
+fn list_events(ctx: &Context, g_id: i64, page: i64) -> PaginationResult<(i64, NaiveDateTime, String)> {
+    use schema::guild_event::dsl::*;
+
+    with_pool(&ctx, |pool| guild_event
+              .filter(guild_id.eq(g_id))
+              .order(when)
+              .select((id, when, title))
+              .paginate(page)
+              .load_and_count_pages(&pool)
+              .unwrap())
+}
+
+
+fn get_event_by_index(ctx: &Context, g_id: i64, idx: i64) -> Option<GuildEvent> {
+    use schema::guild_event::dsl::*;
+
+    // same ordering as `list_events`
+    with_pool(&ctx, |pool| guild_event
+              .filter(guild_id.eq(g_id))
+              .order(when)
+              .offset(idx - 1)
+              .first(&pool)
+              .ok())
+}
+
+
+fn delete_event(ctx: &Context, e_id: i64) {
+    use schema::guild_event::dsl::*;
+
+    with_pool(&ctx, |pool| diesel::delete(guild_event.find(e_id))
+              .execute(&pool)
+              .unwrap());
+}
+
+
+/// Count the attendees of a list of events, in the same order as the ids
+fn count_rsvps(ctx: &Context, e_ids: &[i64]) -> Vec<usize> {
+    use schema::guild_event_rsvp::dsl::*;
+
+    let rsvps: Vec<i64> = with_pool(&ctx, |pool| guild_event_rsvp
+                                    .filter(event_id.eq_any(e_ids))
+                                    .select(event_id)
+                                    .load(&pool)
+                                    .unwrap());
+
+    e_ids
+        .iter()
+        .map(|e_id| rsvps.iter().filter(|&r| r == e_id).count())
+        .collect()
+}
+
+
+/// Add or remove an attendee when someone reacts to an event announcement
+pub fn process_rsvp(ctx: &Context, reaction: &Reaction, attending: bool) {
+    use models::NewGuildEventRsvp;
+    use schema::{guild_event, guild_event_rsvp};
+
+    match reaction.emoji {
+        ReactionType::Unicode(ref s) if *s == RSVP_EMOJI.to_string() => (),
+        _ => return,
+    }
+
+    let bot_id = log_time!(with_cache(|c| c.user.id), "with_cache: get bot id");
+
+    if reaction.user_id == bot_id {
+        return;
+    }
+
+    let e_id: i64 = match with_pool(&ctx, |pool| guild_event::table
+                                    .filter(guild_event::message_id.eq(reaction.message_id.0 as i64))
+                                    .select(guild_event::id)
+                                    .first(&pool)) {
+        Ok(id) => id,
+        Err(_) => return,
+    };
+
+    let u_id = reaction.user_id.0 as i64;
+
+    with_pool(&ctx, |pool| if attending {
+        diesel::insert_into(guild_event_rsvp::table)
+            .values(&NewGuildEventRsvp { event_id: e_id, user_id: u_id })
+            .on_conflict_do_nothing()
+            .execute(&pool)
+            .expect("Could not insert rsvp")
+    } else {
+        diesel::delete(guild_event_rsvp::table
+                       .filter(guild_event_rsvp::event_id.eq(e_id))
+                       .filter(guild_event_rsvp::user_id.eq(u_id)))
+            .execute(&pool)
+            .expect("Could not delete rsvp")
+    });
+}
+
+
+command!(event_create_cmd(ctx, msg, args) {
+    use commands::reminders::human_timedelta;
+
+    let now = Utc::now().naive_utc();
+    let (when, title) = recognise_date(now, args.full())?;
+
+    if when <= now {
+        return Err("Events cannot start in the past.".into());
+    }
+
+    if title.is_empty() {
+        return Err("Events need a title.".into());
+    }
+
+    if title.len() > 256 {
+        return Err("Event titles cannot be longer than 256 characters.".into());
+    }
+
+    let delta = when.signed_duration_since(now);
+
+    let announcement = msg.channel_id.send_message(
+        |m| m.embed(
+            |e| e
+                .colour(0x3fb950)
+                .title(&title)
+                .description(format!("React with {} to attend, attendees will be pinged before the event.", RSVP_EMOJI))
+                .field("When", format!("{} UTC", when.format("%Y-%m-%d %H:%M")), true)
+                .field("Starts in", human_timedelta(&delta), true)
+                .field("Organiser", msg.author.mention(), true)
+        ))?;
+
+    void!(announcement.react(RSVP_EMOJI));
+
+    insert_event(&ctx, msg.guild_id.unwrap().0 as i64, msg.channel_id.0 as i64,
+                 announcement.id.0 as i64, msg.author.id.0 as i64, &title, when);
+});
+
+
+command!(events_cmd(ctx, msg, args) {
+    let page = args.single::<i64>().unwrap_or(1);
+
+    if page <= 0 {
+        return Err("That page does not exist.".into());
+    }
+
+    let events = list_events(&ctx, msg.guild_id.unwrap().0 as i64, page);
+
+    if !events.page_exists() {
+        return Err("That page does not exist or no events exist for this server.".into());
+    }
+
+    let e_ids: Vec<_> = events.results.iter().map(|&(id, _, _)| id).collect();
+    let counts = count_rsvps(&ctx, &e_ids);
+
+    let block = events.block(|(ref id, ref w, ref t), i| {
+        let pos = e_ids.iter().position(|e| e == id).unwrap();
+        format!("{:>3} | {} | {:>3} attending | {}", i, w.format("%Y-%m-%d %H:%M"), counts[pos], t)
+    });
+
+    let message = MessageBuilder::new()
+        .push_line("Upcoming events (times are UTC):")
+        .push(block);
+
+    void!(say(msg.channel_id, message));
+});
+
+
+command!(event_delete_cmd(ctx, msg, args) {
+    let index = get_arg!(args, single, usize, index) as i64;
+
+    let event = get_event_by_index(&ctx, msg.guild_id.unwrap().0 as i64, index)
+        .ok_or("That event doesn't exist.")?;
+
+    let has_manage_messages = log_time!(with_cache(
+        |cache| cache.guild(msg.guild_id.unwrap()).map_or(
+            false,
+            |g| g.read().member_permissions(msg.author.id).manage_messages()
+        )
+    ), "with_cache: has_manage_messages");
+
+    if has_manage_messages || (event.author_id as u64 == msg.author.id.0) {
+        delete_event(&ctx, event.id);
+        void!(say(msg.channel_id, MessageBuilder::new().push("Cancelled the event: ").push_safe(&event.title)));
+    } else {
+        void!(say(msg.channel_id, "You are not the organiser of this event or do not have manage messages."));
+    }
+});
+
+
+command!(event_lead_times_cmd(ctx, msg, args) {
+    let g_id = msg.guild_id.unwrap().0 as i64;
+
+    if args.is_empty() {
+        let current = get_lead_times(&ctx, g_id);
+        let times = current.iter().map(|m| format!("{}m", m)).join(", ");
+        void!(say(msg.channel_id, format!("Attendees are pinged this long before events: {}", times)));
+        return Ok(());
+    }
+
+    let mut times = parse_lead_times(args.full()).ok_or("Could not parse the lead times, use values such as 15m, 2h or 1d separated by commas.")?;
+
+    if times.len() > 5 {
+        return Err("You can have at most 5 lead times.".into());
+    }
+
+    times.sort_unstable_by(|a, b| b.cmp(a));
+    times.dedup();
+
+    set_lead_times(&ctx, g_id, &times);
+
+    void!(say(msg.channel_id, "Updated the event lead times, they apply to new events."));
+});
+
+
+pub fn setup_events(client: &mut Client, frame: StandardFramework) -> StandardFramework {
+    frame.group("Events",
+                register(client, |g| g
+                .guild_only(true)
+                .command("event_create", |c| c
+                         .cmd(event_create_cmd)
+                         .desc(concat!(
+                             "Announce an event, members can react to the announcement to attend ",
+                             "and will be pinged before it starts. ",
+                             "Times are given in the same way as the `remind` command."))
+                         .example("friday at 8pm Movie night")
+                         .usage("{when} {title}")
+                         .batch_known_as(&["create_event"])
+                )
+                .command("events", |c| c
+                         .cmd(events_cmd)
+                         .desc("List upcoming events in this guild.")
+                         .usage("{page}")
+                         .batch_known_as(&["list_events", "event_list"])
+                )
+                .command("event_delete", |c| c
+                         .cmd(event_delete_cmd)
+                         .desc("Cancel an event by index, only the organiser or a member with manage messages can do this.")
+                         .usage("{index}")
+                         .batch_known_as(&["delete_event", "event_cancel"])
+                )
+                .command("event_lead_times", |c| c
+                         .cmd(event_lead_times_cmd)
+                         .desc("View or set how long before an event attendees are pinged.")
+                         .example("1d, 2 hours, 15m")
+                         .usage("{lead times, separated by commas}")
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_lead_time() {
+        assert_eq!(parse_lead_time("15"), Some(15));
+        assert_eq!(parse_lead_time("15m"), Some(15));
+        assert_eq!(parse_lead_time("2 hours"), Some(120));
+        assert_eq!(parse_lead_time("1d"), Some(1440));
+        assert_eq!(parse_lead_time("1w"), Some(10080));
+        assert_eq!(parse_lead_time("soon"), None);
+        assert_eq!(parse_lead_time("99999999999d"), None);
+
+        assert_eq!(parse_lead_times("1d, 2 hours,15m"), Some(vec![1440, 120, 15]));
+        assert_eq!(parse_lead_times("1d, soon"), None);
+    }
+}
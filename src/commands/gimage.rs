@@ -6,6 +6,7 @@ use serenity::{framework::standard::StandardFramework, prelude::*};
 use typemap::Key;
 use failure::Error;
 use utils::send_message;
+use commands::register;
 
 
 #[derive(Debug, Fail)]
@@ -110,17 +111,19 @@ command!(gimage_cmd(ctx, msg, args) {
 
 
 pub fn setup_gimage(client: &mut Client, frame: StandardFramework) -> StandardFramework {
-    let mut data = client.data.lock();
-    data.insert::<ImageClient>(ImageClient::generate());
+    {
+        let mut data = client.data.lock();
+        data.insert::<ImageClient>(ImageClient::generate());
+    }
 
     frame
         .bucket("gimage_bucket", 3, 10, 2)
-        .group("GImage", |g| {
+        .group("GImage", register(client, |g| {
             g.bucket("gimage_bucket").command("gimage", |c| {
                 c.cmd(gimage_cmd)
                     .desc("Search google for images")
                     .example("memes")
                     .usage("{search string}")
             })
-        })
+        }))
 }
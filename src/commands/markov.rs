@@ -28,6 +28,7 @@ use utils::{HistoryIterator, say, send_message, get_random_members};
 use itertools::Itertools;
 use typemap::Key;
 use lru_cache::LruCache;
+use commands::register;
 
 
 struct MarkovStateCache;
@@ -388,7 +389,7 @@ pub fn setup_markov(client: &mut Client, frame: StandardFramework) -> StandardFr
     frame
         .simple_bucket("markov_fill_bucket", 60 * 60) // once each hour
         .group("Markov",
-               |g| g
+               register(client, |g| g
                .guild_only(true)
                .command("markov", |c| c
                         .cmd(markov_cmd)
@@ -424,5 +425,5 @@ pub fn setup_markov(client: &mut Client, frame: StandardFramework) -> StandardFr
                         .desc("Strip crap from the db.")
                         .owners_only(true)
                         .help_available(false))
-    )
+    ))
 }
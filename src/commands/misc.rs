@@ -12,6 +12,7 @@ use std::num::Wrapping;
 use std::time;
 use utils::{and_comma_split, say, send_message, try_resolve_user, with_pool};
 use whirlpool::{Digest, Whirlpool};
+use commands::register;
 
 fn process_usage() -> f64 {
     use std::thread;
@@ -242,10 +243,10 @@ command!(count_generics_cmd(_ctx, msg) {
     void!(say(msg.channel_id, format!("There are {} guilds that have generic in their name", count)));
 });
 
-pub fn setup_misc(_client: &mut Client, frame: StandardFramework) -> StandardFramework {
+pub fn setup_misc(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     frame
         .group("Misc",
-               |g| g
+               register(client, |g| g
                .command("stats", |c| c
                         .cmd(status_cmd)
                         .desc("Bot stats")
@@ -282,9 +283,9 @@ pub fn setup_misc(_client: &mut Client, frame: StandardFramework) -> StandardFra
                         .cmd(ping_cmd)
                         .desc("Ping discord")
                )
-        )
+        ))
         .group("Hidden",
-               |g| g
+               register(client, |g| g
                .help_available(false)
                .command("stando", |c| c
                         .cmd(stando)
@@ -302,5 +303,5 @@ pub fn setup_misc(_client: &mut Client, frame: StandardFramework) -> StandardFra
                         .cmd(count_generics_cmd)
                         .desc("what is this bot useful for anyway?")
                )
-        )
+        ))
 }
@@ -1,9 +1,103 @@
 pub mod tags;
 pub mod admin;
 pub mod reminders;
+pub mod events;
+pub mod announcements;
+pub mod responders;
 pub mod markov;
 pub mod misc;
 pub mod booru;
 pub mod prefixes;
 pub mod gimage;
 pub mod aliases;
+
+use serenity::{
+    prelude::*,
+    framework::standard::{
+        Command,
+        CommandGroup,
+        CommandOrAlias,
+        CreateGroup,
+    },
+};
+use std::{collections::HashMap, sync::Arc};
+use ::CommandRegistry;
+
+
+/// The commands registered with the framework, by each of their names
+#[derive(Default)]
+pub struct Commands {
+    commands: HashMap<String, Arc<Command>>,
+    buckets: HashMap<String, String>,
+}
+
+
+impl Commands {
+    /// Record the commands of a group, alternative names refer to the command they are for
+    fn add_group(&mut self, group: &CommandGroup) {
+        for (name, command) in &group.commands {
+            let command = match *command {
+                CommandOrAlias::Command(ref c) => c.clone(),
+                CommandOrAlias::Alias(ref target) => match group.commands.get(target) {
+                    Some(&CommandOrAlias::Command(ref c)) => c.clone(),
+                    _ => continue,
+                },
+            };
+
+            // commands in a bucketed group might not record the bucket themselves
+            if let Some(bucket) = command.options().bucket.clone().or_else(|| group.bucket.clone()) {
+                self.buckets.insert(name.clone(), bucket);
+            }
+
+            self.commands.insert(name.clone(), command);
+        }
+    }
+
+    /// Find a command by any of its names
+    pub fn get(&self, name: &str) -> Option<Arc<Command>> {
+        self.commands.get(name).cloned()
+    }
+
+    /// The rate limit bucket of a command, if it has one
+    pub fn bucket(&self, name: &str) -> Option<&str> {
+        self.buckets.get(name).map(String::as_str)
+    }
+
+    /// Check if a name is used by a command, the help command isn't part of a group so is checked for separately
+    pub fn is_command(&self, name: &str) -> bool {
+        name == "help" || self.commands.contains_key(name)
+    }
+}
+
+
+/// Wrap the builder of a command group so its commands are recorded in the `CommandRegistry` when it is built
+pub fn register<'a, F>(client: &'a Client, f: F) -> impl FnOnce(CreateGroup) -> CreateGroup + 'a
+    where F: FnOnce(CreateGroup) -> CreateGroup + 'a
+{
+    move |g| {
+        let g = f(g);
+
+        let mut data = client.data.lock();
+        data.get_mut::<CommandRegistry>().unwrap().add_group(&g.0);
+
+        g
+    }
+}
+
+
+/// Check if a name is used by a command
+pub fn is_command(ctx: &Context, name: &str) -> bool {
+    ctx.data.lock().get::<CommandRegistry>().unwrap().is_command(name)
+}
+
+
+/// Find a registered command by any of its names
+pub fn get_command(ctx: &Context, name: &str) -> Option<Arc<Command>> {
+    ctx.data.lock().get::<CommandRegistry>().unwrap().get(name)
+}
+
+
+/// Find the rate limit bucket of a command by any of its names
+pub fn command_bucket(ctx: &Context, name: &str) -> Option<String> {
+    ctx.data.lock().get::<CommandRegistry>().unwrap().bucket(name).map(String::from)
+}
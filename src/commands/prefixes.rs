@@ -15,6 +15,7 @@ use itertools::Itertools;
 use ::PgConnectionManager;
 use ::PrefixCache;
 use utils::say;
+use commands::register;
 
 
 fn delete_prefix(ctx: &Context, p: &str, g_id: GuildId) {
@@ -109,9 +110,9 @@ command!(delete_prefix_cmd(ctx, msg, args) {
 });
 
 
-pub fn setup_prefixes(_client: &mut Client, frame: StandardFramework) -> StandardFramework {
+pub fn setup_prefixes(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     frame.group("Prefixes",
-                |g| g
+                register(client, |g| g
                 .guild_only(true)
                 .command("list_prefixes",
                          |c| c
@@ -134,5 +135,5 @@ pub fn setup_prefixes(_client: &mut Client, frame: StandardFramework) -> Standar
                          .usage("{prefix}")
                          .required_permissions(Permissions::ADMINISTRATOR)
                 )
-    )
+    ))
 }
@@ -4,221 +4,187 @@ use serenity::{
         StandardFramework,
         CommandError,
     },
+    model::{
+        channel::{Channel, Message},
+        id::{ChannelId, GuildId, UserId},
+        permissions::Permissions,
+    },
     utils::{
+        with_cache,
         MessageBuilder,
     },
 };
 use diesel::prelude::*;
 use diesel;
 use ::PgConnectionManager;
+use models::Reminder;
+use itertools::Itertools;
 use regex::Regex;
-use chrono::{NaiveDateTime, Utc, Datelike, Duration, NaiveDate};
+use chrono::{NaiveDateTime, Utc, Duration};
+use commands::register;
 use utils::{
     say,
     with_pool,
+    dates::{recognise_date, DateError},
     pagination::{
         PaginationResult,
         Paginate,
+        parse_list_args,
     },
 };
 
 
-/// Parse a text message into a datetime and the remaining string.
-fn recognise_date(mut base_time: NaiveDateTime, date: &str) -> Result<(NaiveDateTime, String), CommandError> {
-    // parse out jan(uary) ... stuff etc
+/// Split the user and role mentions off the start of a reminder.
+///
+/// Returns the mentioned ids along with if they are a role, and the remaining text.
+fn split_targets(text: &str) -> (Vec<(i64, bool)>, &str) {
     lazy_static! {
-        static ref TDIFF_RE: Regex = Regex::new(concat!(
-            r"(?:in\s*)?",
-            r"(?P<value>\d+)\s*",
-            r"(?P<period>",
-            r"y(?:ears?)?|",
-            r"M|",
-            r"months?|",
-            r"w(?:eeks?)?|",
-            r"d(?:ays?)?|",
-            r"h(?:r|(?:our?))?s?|",
-            r"m(?:in(?:ute)?s?)?|",
-            r"s(?:ec(?:ond)?s?)?)",
-            r"\b"
-        )).unwrap();
-
-        static ref TDAY_RE: Regex = Regex::new(r"(?i)(?:on\s*)?(?P<day>monday|tuesday|wednesday|thursday|friday|saturday|sunday)\b").unwrap();
-
-        static ref DMONTH_RE: Regex = Regex::new(concat!(
-            r"(?i)(?:on\s*)?",
-            r"(?P<month>",
-            r"jan(?:uary)?|",
-            r"feb(?:ruary)?|",
-            r"mar(?:ch)?|",
-            r"apr(?:il)?|",
-            r"may|",
-            r"june?|",
-            r"july?|",
-            r"aug(?:ust)?|",
-            r"sep(?:tember)?|",
-            r"oct(?:ober)?|",
-            r"nov(?:ember)?|",
-            r"dec(?:ember)?)",
-            r"\s*(?P<value>\d+)",
-            r"(?:st|nd|rd|th)?",
-            r"\b"
-        )).unwrap();
-
-        static ref TOMORROW_RE: Regex = Regex::new(r"(?i)tomorrow\b").unwrap();
-    }
-
-    let mut has_parsed = false;
-
-    if TOMORROW_RE.is_match(date) {
-        base_time += Duration::days(1);
-        has_parsed = true;
+        static ref MENTION_RE: Regex = Regex::new(r"^\s*<@(?P<role>&)?!?(?P<id>\d+)>").unwrap();
     }
 
-    let mut tdiff_parsed = false;
+    let mut targets = Vec::new();
+    let mut rest = text;
 
-    for caps in TDIFF_RE.captures_iter(date) {
-        if has_parsed {
-            return Err("Cannot mix 'tomorrow' and delta times.".into());
-        }
+    while let Some(caps) = MENTION_RE.captures(rest) {
+        let id = match (&caps["id"]).parse::<u64>() {
+            Ok(id) => id as i64,
+            Err(_) => break,
+        };
 
-        let val = i64::from((&caps["value"]).parse::<u32>()?);
-        let per = &caps["period"];
+        targets.push((id, caps.name("role").is_some()));
+        rest = &rest[caps.get(0).unwrap().end()..];
+    }
 
-        if per == "M" || per.starts_with("mon") { // special case for months
-            let yr = base_time.year();
-            let mn = base_time.month0() + (val as u32);
+    (targets, rest.trim_start())
+}
 
-            // muh sign conversions
-            let yr = (yr as u32 + mn / 12) as i32;
-            let mn = mn % 12;
 
-            base_time = base_time
-                .with_year(yr).ok_or("Invalid year value from months.")?
-                .with_month0(mn).ok_or("Invalid month value.")?;
-        } else {
-            base_time = match &per[..1] {
-                "y" => {
-                    let yr = base_time.year() + (val as i32);
-                    base_time.with_year(yr).ok_or("Invalid year value.")?
-                },
-                "w" => base_time + Duration::weeks(val),
-                "d" => base_time + Duration::days(val),
-                "h" => base_time + Duration::hours(val),
-                "m" => base_time + Duration::minutes(val),
-                "s" => base_time + Duration::seconds(val),
-                _   => unreachable!(),
-            };
-        }
-        tdiff_parsed = true;
-    }
+/// Insert a reminder created by a message, it will be delivered in the channel of the message.
+fn insert_reminder(ctx: &Context, msg: &Message, when: NaiveDateTime, now: NaiveDateTime,
+                   text: &str, targets: &[(i64, bool)]) {
+    use models::{NewReminder, NewReminderTarget};
+    use schema::{reminder, reminder_target};
 
-    has_parsed |= tdiff_parsed;
+    let reminder = NewReminder {
+        user_id: msg.author.id.0 as i64,
+        channel_id: msg.channel_id.0 as i64,
+        text,
+        started: &now,
+        when: &when,
+        message_id: Some(msg.id.0 as i64),
+        guild_id: msg.guild_id.map(|g| g.0 as i64),
+    };
 
-    if let Some(caps) = TDAY_RE.captures(date) {
-        if has_parsed {
-            return Err("Cannot mix weekday and delta time.".into());
-        }
+    with_pool(&ctx, |pool| {
+        let r_id: i64 = diesel::insert_into(reminder::table)
+            .values(&reminder)
+            .returning(reminder::id)
+            .get_result(&pool)
+            .expect("Could not insert reminder");
+
+        let new_targets: Vec<_> = targets
+            .iter()
+            .map(|&(target_id, is_role)| NewReminderTarget {
+                reminder_id: r_id,
+                target_id,
+                is_role,
+            })
+            .collect();
+
+        diesel::insert_into(reminder_target::table)
+            .values(&new_targets)
+            .on_conflict_do_nothing()
+            .execute(&pool)
+            .expect("Could not insert reminder targets");
+    });
+}
 
-        let day = match &(&caps["day"])[..2] {
-            "mo" => 0,
-            "tu" => 1,
-            "we" => 2,
-            "th" => 3,
-            "fr" => 4,
-            "sa" => 5,
-            "su" => 6,
-            _     => unreachable!(),
-        };
 
-        let current_day = base_time.weekday().num_days_from_monday();
+fn get_remind_others_perms(ctx: &Context, g_id: i64) -> Permissions {
+    use schema::guild::dsl::*;
 
-        let delta = (day - current_day) % 7;  // if in past, wrap around
+    let bits: i64 = with_pool(&ctx, |pool| guild
+                              .find(g_id)
+                              .select(remind_others_perms)
+                              .first(&pool)
+                              .unwrap_or_else(|_| Permissions::MANAGE_MESSAGES.bits() as i64));
 
-        base_time += Duration::days(i64::from(delta));
+    Permissions::from_bits_truncate(bits as u64)
+}
 
-        has_parsed = true;
-    }
 
-    for caps in DMONTH_RE.captures_iter(date) {
-        if has_parsed {
-            return Err("Cannot mix deltas or have multiple dates and month values.".into());
-        }
+fn set_remind_others_perms(ctx: &Context, g_id: i64, perms: Permissions) {
+    use schema::guild::dsl::*;
 
-        let month = &caps["month"];
-        let day = (&caps["value"]).parse::<u32>()?;
-
-        let month_num = match &month[..3] {
-            "jan" => 1,
-            "feb" => 2,
-            "mar" => 3,
-            "apr" => 4,
-            "may" => 5,
-            "jun" => 6,
-            "jul" => 7,
-            "aug" => 8,
-            "sep" => 9,
-            "oct" => 10,
-            "nov" => 11,
-            "dec" => 12,
-            _     => unreachable!(),
-        };
+    with_pool(&ctx, |pool| diesel::update(guild.find(g_id))
+              .set(remind_others_perms.eq(perms.bits() as i64))
+              .execute(&pool)
+              .unwrap());
+}
 
-        let current_month_num = base_time.month();
 
-        let updated_value = if current_month_num <= month_num {
-            NaiveDate::from_yo(base_time.year(), 1)
-        } else {
-            NaiveDate::from_yo(base_time.year() + 1, 1)
-        };
+fn permission_from_name(name: &str) -> Option<Permissions> {
+    let perms = match name.to_lowercase().replace(' ', "_").as_str() {
+        "none" | "everyone" => Permissions::empty(),
+        "administrator" => Permissions::ADMINISTRATOR,
+        "manage_guild" => Permissions::MANAGE_GUILD,
+        "manage_channels" => Permissions::MANAGE_CHANNELS,
+        "manage_roles" => Permissions::MANAGE_ROLES,
+        "manage_messages" => Permissions::MANAGE_MESSAGES,
+        "mention_everyone" => Permissions::MENTION_EVERYONE,
+        "kick_members" => Permissions::KICK_MEMBERS,
+        "ban_members" => Permissions::BAN_MEMBERS,
+        _ => return None,
+    };
 
-        base_time = updated_value.and_hms(0, 0, 0)
-            .with_month(month_num).ok_or("Bad month provided.")?
-            .with_day(day).ok_or("Bad day number provided for that month.")?;
+    Some(perms)
+}
 
-        has_parsed = true;
-    }
 
-    if !has_parsed {
-        return Err("Could not parse time.".into());
-    }
-
-    let replaced = TDIFF_RE.replace_all(date, "");
-    let replaced = TDAY_RE.replace_all(&replaced, "");
-    let replaced = DMONTH_RE.replace_all(&replaced, "");
-    let replaced = TOMORROW_RE.replace_all(&replaced, "");
-    let replaced = replaced
-        .trim()
-        .to_owned();
+fn list_reminders(ctx: &Context, u_id: i64, page: i64) -> PaginationResult<(NaiveDateTime, i64, String)> {
+    use schema::reminder::dsl::*;
 
-    Ok((base_time, replaced))
+    with_pool(&ctx, |pool| reminder.filter(user_id.eq(u_id))
+              .order(when)
+              .select((when, channel_id, text))
+              .paginate(page)
+              .load_and_count_pages(&pool)
+              .unwrap())
 }
 
 
-fn insert_reminder(ctx: &Context, u_id: i64, c_id: i64, when: NaiveDateTime, now: NaiveDateTime, msg: &str) {
-    use models::NewReminder;
-    use schema::reminder;
+/// The roles a user has in each guild the bot can see
+fn member_role_ids(u_id: UserId) -> Vec<i64> {
+    log_time!(with_cache(
+        |cache| cache.guilds
+            .values()
+            .filter_map(|g| g.read().members.get(&u_id).map(|m| m.roles.clone()))
+            .flatten()
+            .map(|r| r.0 as i64)
+            .collect()
+    ), "with_cache: member_role_ids")
+}
 
-    let reminder = NewReminder {
-        user_id: u_id,
-        channel_id: c_id,
-        text: msg,
-        started: &now,
-        when: &when,
-    };
 
-    with_pool(&ctx, |pool| diesel::insert_into(reminder::table)
-              .values(&reminder)
-              .execute(&pool)
-              .expect("Could not insert reminder"));
-}
+/// List reminders other users have created for a user, directly or through one of their roles
+fn list_reminders_for(ctx: &Context, u_id: i64, role_ids: &[i64], page: i64) -> PaginationResult<(NaiveDateTime, i64, i64, String)> {
+    use schema::{reminder, reminder_opt_out, reminder_target};
 
+    let targeted = reminder_target::table
+        .filter(reminder_target::target_id.eq(u_id).and(reminder_target::is_role.eq(false))
+                .or(reminder_target::target_id.eq_any(role_ids).and(reminder_target::is_role.eq(true))))
+        .select(reminder_target::reminder_id);
 
-fn list_reminders(ctx: &Context, u_id: i64, page: i64) -> PaginationResult<(NaiveDateTime, String)> {
-    use schema::reminder::dsl::*;
+    let opted_out = reminder_opt_out::table
+        .filter(reminder_opt_out::user_id.eq(u_id))
+        .select(reminder_opt_out::reminder_id);
 
-    with_pool(&ctx, |pool| reminder.filter(user_id.eq(u_id))
-              .order(when)
-              .select((when, text))
+    with_pool(&ctx, |pool| reminder::table
+              .filter(reminder::id.eq_any(targeted))
+              .filter(diesel::dsl::not(reminder::id.eq_any(opted_out)))
+              .filter(reminder::user_id.ne(u_id))
+              .order((reminder::when, reminder::id))
+              .select((reminder::when, reminder::channel_id, reminder::user_id, reminder::text))
               .paginate(page)
               .load_and_count_pages(&pool)
               .unwrap())
@@ -246,6 +212,95 @@ fn delete_reminder(ctx: &Context, u_id: i64, idx: i64) -> bool {
 }
 
 
+#[derive(QueryableByName)]
+struct EditedReminder {
+    #[sql_type = "diesel::sql_types::Timestamp"]
+    when: NaiveDateTime,
+}
+
+
+/// Update the time and/or text of a reminder by index, returning the new time of the reminder.
+///
+/// A reminder given a new time counts as started `now`.
+fn edit_reminder(ctx: &Context, u_id: i64, idx: i64, now: NaiveDateTime,
+                 new_when: Option<NaiveDateTime>, new_text: Option<&str>) -> Option<NaiveDateTime> {
+    use diesel::sql_types::{BigInt, Nullable, Timestamp, Varchar};
+
+    let pool = extract_pool!(&ctx);
+
+    // same ordering as `delete_reminder`
+    let edited = diesel::sql_query(r#"
+        UPDATE "reminder"
+        SET "when" = COALESCE($3, "when"), "text" = COALESCE($4, "text"), "started" = COALESCE($5, "started")
+        WHERE id in (
+            SELECT id FROM (
+                SELECT id, row_number() OVER (ORDER BY "when" ASC) as row_num
+                FROM "reminder" WHERE "user_id" = $1
+            ) AS s WHERE s.row_num = $2)
+        RETURNING "when"
+   "#)
+        .bind::<BigInt, i64>(u_id)
+        .bind::<BigInt, i64>(idx)
+        .bind::<Nullable<Timestamp>, _>(new_when)
+        .bind::<Nullable<Varchar>, _>(new_text)
+        .bind::<Nullable<Timestamp>, _>(new_when.map(|_| now))
+        .load::<EditedReminder>(pool)
+        .unwrap();
+
+    edited.into_iter().next().map(|r| r.when)
+}
+
+
+/// Opt a user out of a reminder somebody else created for them or one of their roles
+fn opt_out_reminder(ctx: &Context, u_id: i64, role_ids: &[i64], idx: i64) -> bool {
+    use diesel::sql_types::{Array, BigInt};
+
+    let pool = extract_pool!(&ctx);
+
+    // same ordering as `list_reminders_for`
+    let amount = diesel::sql_query(r#"
+        INSERT INTO "reminder_opt_out" (reminder_id, user_id)
+        SELECT id, $1 FROM (
+            SELECT r.id, row_number() OVER (ORDER BY r."when" ASC, r.id ASC) as row_num
+            FROM "reminder" r
+            WHERE r.user_id <> $1
+              AND EXISTS (SELECT 1 FROM "reminder_target" t
+                          WHERE t.reminder_id = r.id
+                            AND ((t.target_id = $1 AND NOT t.is_role) OR (t.is_role AND t.target_id = ANY($3))))
+              AND NOT EXISTS (SELECT 1 FROM "reminder_opt_out" o
+                              WHERE o.reminder_id = r.id AND o.user_id = $1)
+        ) AS s WHERE s.row_num = $2
+        ON CONFLICT DO NOTHING
+   "#)
+        .bind::<BigInt, i64>(u_id)
+        .bind::<BigInt, i64>(idx)
+        .bind::<Array<BigInt>, _>(role_ids)
+        .execute(pool);
+
+    amount.unwrap() > 0
+}
+
+
+fn get_reminders(ctx: &Context, u_id: i64) -> Vec<Reminder> {
+    use schema::reminder::dsl::*;
+
+    with_pool(&ctx, |pool| reminder.filter(user_id.eq(u_id))
+              .order(when)
+              .load(&pool)
+              .unwrap())
+}
+
+
+/// A readable name for the channel a message will be delivered in
+pub fn channel_label(c_id: i64) -> String {
+    match ChannelId::from(c_id as u64).to_channel_cached() {
+        Some(Channel::Guild(c)) => format!("#{}", c.read().name),
+        Some(Channel::Private(_)) => "DM".to_owned(),
+        _ => c_id.to_string(),
+    }
+}
+
+
 pub fn human_timedelta(delta: &Duration) -> String {
     use utils::and_comma_split;
 
@@ -275,41 +330,96 @@ pub fn human_timedelta(delta: &Duration) -> String {
 
 
 command!(remind_cmd(ctx, msg, args) {
-    let time = args.full();
+    let (mentioned, time) = split_targets(args.full());
+
+    let author_id = msg.author.id.0 as i64;
+
+    let targets = if mentioned.is_empty() {
+        vec![(author_id, false)]
+    } else {
+        mentioned
+    };
+
+    let reminds_others = targets.iter().any(|&(id, is_role)| is_role || id != author_id);
+
+    if reminds_others {
+        let g_id = msg.guild_id.ok_or("Reminding other people can only be done in guilds.")?;
+
+        let required = get_remind_others_perms(&ctx, g_id.0 as i64);
+
+        let allowed = log_time!(with_cache(
+            |cache| cache.guild(g_id).map_or(
+                false,
+                |g| g.read().member_permissions(msg.author.id).contains(required)
+            )
+        ), "with_cache: has_remind_others_perms");
+
+        if !allowed {
+            return Err(format!("Reminding other people requires the permissions: {:?}", required).into());
+        }
+    }
 
     let now = Utc::now().naive_utc();
     let (when, replaced) = recognise_date(now, &time)?;
 
-    insert_reminder(&ctx, msg.author.id.0 as i64,
-                    msg.channel_id.0 as i64,
-                    when, now, &replaced);
+    insert_reminder(&ctx, &msg, when, now, &replaced, &targets);
 
     let delta = when.signed_duration_since(now);
 
-    void!(say(msg.channel_id, format!("Okay, I'll remind you about '{}' in {}", replaced, human_timedelta(&delta))));
+    let response = if reminds_others {
+        format!("Okay, I'll remind them about '{}' in {}", replaced, human_timedelta(&delta))
+    } else {
+        format!("Okay, I'll remind you about '{}' in {}", replaced, human_timedelta(&delta))
+    };
+
+    void!(say(msg.channel_id, response));
 });
 
 
 command!(remind_list(ctx, msg, args) {
-    let page = args.single::<i64>().unwrap_or(1);
+    use utils::names_for_members;
 
-    if page <= 0 {
-        return Err("That page does not exist.".into());
-    }
+    let (list, page) = parse_list_args(args.full(), &["mine", "for_me"])?;
 
-    let reminders = list_reminders(&ctx, msg.author.id.0 as i64, page);
+    let mut message = MessageBuilder::new()
+        .push("Reminders for ")
+        .mention(&msg.author)
+        .push_line(": ");
 
-    if !reminders.page_exists() {
-        return Err("That page does not exits or no reminders for this user.".into());
-    }
+    if list == "mine" {
+        let created = list_reminders(&ctx, msg.author.id.0 as i64, page);
 
-    let block = reminders.block(|(ref w, ref t), i| format!("{:>3} | {} | {}", i, w, t));
+        if !created.page_exists() {
+            return Err("That page does not exist or you have not created any reminders.".into());
+        }
 
-    let message = MessageBuilder::new()
-        .push("Reminders for ")
-        .mention(&msg.author)
-        .push_line(": ")
-        .push(block);
+        let block = created.block(|(ref w, ref c, ref t), i| format!("{:>3} | {} | {} | {}", i, w, channel_label(*c), t));
+
+        message = message
+            .push_line("Created by you (use `reminder_list for_me` for reminders others made for you):")
+            .push(block);
+    } else {
+        let role_ids = member_role_ids(msg.author.id);
+        let for_user = list_reminders_for(&ctx, msg.author.id.0 as i64, &role_ids, page);
+
+        if !for_user.page_exists() {
+            return Err("That page does not exist or nobody made reminders for you.".into());
+        }
+
+        let creator_ids: Vec<u64> = for_user.results.iter().map(|&(_, _, u, _)| u as u64).collect();
+
+        // outside of a guild this falls back to fetching each user
+        let names = names_for_members(&creator_ids, msg.guild_id.unwrap_or(GuildId(0)));
+
+        let block = for_user.block(|(ref w, ref c, ref u, ref t), i| {
+            let pos = creator_ids.iter().position(|&id| id == *u as u64).unwrap();
+            format!("{:>3} | {} | {} | {}: {}", i, w, channel_label(*c), names[pos], t)
+        });
+
+        message = message
+            .push_line("Created for you (use `reminder_opt_out {index}` to stop one):")
+            .push(block);
+    }
 
     void!(say(msg.channel_id, message));
 });
@@ -327,14 +437,191 @@ command!(delete_reminder_cmd(ctx, msg, args) {
 });
 
 
-pub fn setup_reminders(_client: &mut Client, frame: StandardFramework) -> StandardFramework {
+command!(edit_reminder_cmd(ctx, msg, args) {
+    let index = get_arg!(args, single, usize, index) as i64;
+    let rest = args.rest().trim();
+
+    if rest.is_empty() {
+        return Err("Give a new time, new text or both.".into());
+    }
+
+    let now = Utc::now().naive_utc();
+
+    // if no time can be recognised, the whole thing is the new text
+    let (new_when, new_text) = match recognise_date(now, rest) {
+        Ok((when, replaced)) => (Some(when), if replaced.is_empty() { None } else { Some(replaced) }),
+        Err(DateError::NoDate) => (None, Some(rest.to_owned())),
+        Err(e) => return Err(e.into()),
+    };
+
+    if new_when.map_or(false, |when| when <= now) {
+        return Err("Reminders cannot be moved to the past.".into());
+    }
+
+    match edit_reminder(&ctx, msg.author.id.0 as i64, index, now, new_when, new_text.as_ref().map(|s| s.as_str())) {
+        Some(when) => {
+            let delta = when.signed_duration_since(now);
+            void!(say(msg.channel_id, format!("Edited that reminder, it will go off in {}", human_timedelta(&delta))));
+        },
+        None => void!(say(msg.channel_id, "That reminder didn't exist.")),
+    }
+});
+
+
+command!(export_reminders_cmd(ctx, msg) {
+    use utils::ical::{CalendarEvent, write_calendar};
+
+    let reminders = get_reminders(&ctx, msg.author.id.0 as i64);
+
+    if reminders.is_empty() {
+        return Err("You have no reminders to export.".into());
+    }
+
+    let events: Vec<_> = reminders
+        .into_iter()
+        .map(|r| CalendarEvent {
+            uid: format!("reminder-{}@genericbot", r.id),
+            start: r.when,
+            summary: r.text,
+            rrule: None,
+            alarm: Some(Duration::zero()),
+        })
+        .collect();
+
+    let calendar = write_calendar(&events, &Utc::now().naive_utc());
+
+    msg.channel_id.send_files(vec![(calendar.as_bytes(), "reminders.ics")],
+                              |m| m.content(format!("Exported {} reminders.", events.len())))?;
+});
+
+
+command!(import_reminders_cmd(ctx, msg) {
+    use utils::ical::{parse_calendar, next_occurrence};
+
+    // limits so a huge calendar can't flood the reminder table
+    const MAX_SIZE: u64 = 1_000_000;
+    const MAX_EVENTS: usize = 50;
+
+    let attachment = msg.attachments
+        .iter()
+        .find(|a| a.filename.to_lowercase().ends_with(".ics"))
+        .ok_or("Attach an .ics file to import.")?;
+
+    if attachment.size > MAX_SIZE {
+        return Err("That calendar is too large.".into());
+    }
+
+    let content = String::from_utf8(attachment.download()?)?;
+
+    let now = Utc::now().naive_utc();
+    let author_id = msg.author.id.0 as i64;
+
+    let mut count = 0;
+    let mut skipped = Vec::new();
+
+    for event in parse_calendar(&content) {
+        if count >= MAX_EVENTS {
+            break;
+        }
+
+        let event = match event {
+            Ok(event) => event,
+            Err(e) => {
+                skipped.push(e);
+                continue;
+            },
+        };
+
+        let alarm = event.alarm.unwrap_or_else(Duration::zero);
+
+        let after = match now.checked_sub_signed(alarm) {
+            Some(after) => after,
+            None => {
+                skipped.push(format!("{}: the alarm is too far from the event", event.summary));
+                continue;
+            },
+        };
+
+        // the first occurrence with an alarm still to come
+        let start = try_opt_continue!(next_occurrence(&event, &after));
+        let when = match start.checked_add_signed(alarm) {
+            Some(when) => when,
+            None => {
+                skipped.push(format!("{}: the alarm is too far from the event", event.summary));
+                continue;
+            },
+        };
+
+        if when <= now {
+            continue;
+        }
+
+        let text: String = if event.summary.is_empty() {
+            "Calendar event".to_owned()
+        } else {
+            event.summary.chars().take(2000).collect()
+        };
+
+        insert_reminder(&ctx, &msg, when, now, &text, &[(author_id, false)]);
+        count += 1;
+    }
+
+    let mut content = MessageBuilder::new()
+        .push(format!("Imported {} reminders", count));
+
+    if skipped.is_empty() {
+        content = content.push(".");
+    } else {
+        content = content
+            .push_line(format!(", skipped {} with times I couldn't read:", skipped.len()))
+            .push_codeblock_safe(skipped.iter().take(10).join("\n"), None);
+    }
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(opt_out_reminder_cmd(ctx, msg, args) {
+    let index = get_arg!(args, single, usize, index) as i64;
+
+    let role_ids = member_role_ids(msg.author.id);
+
+    if opt_out_reminder(&ctx, msg.author.id.0 as i64, &role_ids, index) {
+        void!(say(msg.channel_id, "You will no longer be reminded about that."));
+    } else {
+        void!(say(msg.channel_id, "That reminder didn't exist."));
+    };
+});
+
+
+command!(remind_others_perms_cmd(ctx, msg, args) {
+    let g_id = msg.guild_id.unwrap().0 as i64;
+
+    if args.is_empty() {
+        let current = get_remind_others_perms(&ctx, g_id);
+        void!(say(msg.channel_id, format!("Reminding other people currently requires: {:?}", current)));
+        return Ok(());
+    }
+
+    let name = args.full().trim();
+    let perms = permission_from_name(name).ok_or("Unknown permission name.")?;
+
+    set_remind_others_perms(&ctx, g_id, perms);
+
+    void!(say(msg.channel_id, format!("Reminding other people now requires: {:?}", perms)));
+});
+
+
+pub fn setup_reminders(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     frame.group("Reminders",
-                |g| g
+                register(client, |g| g
                 .command("remind", |c| c
                          .cmd(remind_cmd)
                          .desc(r#"Create a reminder to remind you of something at a point in time.
-You can specify deltas, days of the week or months and days.
-For example: "Tomorrow", "3 hours", "july 4th".
+You can specify deltas, days of the week, dates and times of day, and combine them.
+For example: "Tomorrow at noon", "in 2 weeks and 3 days", "next friday at 3pm", "july 4th", "end of month".
+All times are in UTC.
+Mention users or roles before the time to remind them instead, this requires the permission set by `reminder_permission`.
 Valid formats are: ```md
 Time Difference
 ===============
@@ -348,15 +635,20 @@ Time Difference
 
 Specific time
 =============
-- Day of Week (friday)
-- Month + day (july 4th)
-- Tomorrow
+- Day of Week (friday, next tuesday)
+- Month + day (july 4th, 4th of july 2020)
+- Date (2019-12-25)
+- Today | Tomorrow
+- End of week | month | year
+- Time of day (at 3pm, 15:30, at noon)
 ```"#)
                          .example("\"3 hours\" Something")
-                         .usage("{when} {message}"))
+                         .usage("{users or roles...} {when} {message}"))
                 .command("reminder_list", |c| c
                          .cmd(remind_list)
-                         .desc("List your reminders.")
+                         .desc("List the reminders you created, or with `for_me` the ones others created for you.")
+                         .example("for_me 2")
+                         .usage("{mine|for_me} {page}")
                          .batch_known_as(&["reminders_list", "list_reminders"])
                 )
                 .command("reminder_delete", |c| c
@@ -364,18 +656,63 @@ Specific time
                          .desc("Delete a reminder by index")
                          .batch_known_as(&["reminders_delete", "delete_reminder"])
                 )
-    )
+                .command("reminder_edit", |c| c
+                         .cmd(edit_reminder_cmd)
+                         .desc("Edit the time, the text or both of a reminder by index")
+                         .example("2 \"3 hours\" Something else")
+                         .usage("{index} {when} {message}")
+                         .batch_known_as(&["reminders_edit", "edit_reminder"])
+                )
+                .command("reminder_export", |c| c
+                         .cmd(export_reminders_cmd)
+                         .desc("Export your reminders as an iCalendar (.ics) file.")
+                         .batch_known_as(&["reminders_export", "export_reminders"])
+                )
+                .command("reminder_import", |c| c
+                         .cmd(import_reminders_cmd)
+                         .desc("Import future events from an attached iCalendar (.ics) file as reminders.")
+                         .batch_known_as(&["reminders_import", "import_reminders"])
+                )
+                .command("reminder_opt_out", |c| c
+                         .cmd(opt_out_reminder_cmd)
+                         .desc("Stop being reminded about a reminder someone else created for you or one of your roles, by index")
+                         .usage("{index}")
+                         .batch_known_as(&["reminders_opt_out"])
+                )
+                .command("reminder_permission", |c| c
+                         .cmd(remind_others_perms_cmd)
+                         .desc("View or set the permission needed to remind other users and roles.")
+                         .example("manage_messages")
+                         .usage("{permission name | none}")
+                         .guild_only(true)
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+    ))
 }
 
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use chrono::Datelike;
 
     lazy_static! {
         static ref BASE_TIME: NaiveDateTime = NaiveDateTime::from_timestamp(0, 0);
     }
 
+    #[test]
+    fn test_split_targets() {
+        let (targets, rest) = split_targets("<@123> <@!456><@&789> 3 hours do something");
+
+        assert_eq!(targets, vec![(123, false), (456, false), (789, true)]);
+        assert_eq!(rest, "3 hours do something");
+
+        let (targets, rest) = split_targets("3 hours <@123>");
+
+        assert!(targets.is_empty());
+        assert_eq!(rest, "3 hours <@123>");
+    }
+
     #[test]
     fn test_date_parser_delta() {
         let parsed_result = recognise_date(*BASE_TIME, "in 3min do something");
@@ -0,0 +1,545 @@
+use serenity::{
+    prelude::*,
+    framework::standard::StandardFramework,
+    model::{
+        channel::Message,
+        id::{ChannelId, GuildId},
+        permissions::Permissions,
+    },
+    utils::{
+        with_cache,
+        MessageBuilder,
+    },
+};
+use diesel::prelude::*;
+use diesel;
+use ::{ResponderCache, ResponderCooldowns};
+use models::{AutoResponder, NewAutoResponder};
+use regex::{self, Regex, RegexBuilder, RegexSet, RegexSetBuilder};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use commands::{
+    register,
+    announcements::next_word,
+    reminders::channel_label,
+    tags::render_tag,
+};
+use utils::{
+    say,
+    with_pool,
+    pagination::{
+        PaginationResult,
+        Paginate,
+    },
+};
+
+
+/// Guilds may not have more auto responders than this
+const MAX_RESPONDERS: i64 = 50;
+
+/// The longest cooldown a responder can have, in seconds
+const MAX_COOLDOWN: i32 = 60 * 60 * 24;
+
+/// Compiled patterns may not be larger than this many bytes
+const REGEX_SIZE_LIMIT: usize = 1 << 20;
+
+
+/// How the pattern of an auto responder is matched against messages
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum MatchKind {
+    /// The pattern appears anywhere in the message
+    Literal,
+    /// The pattern appears as whole words
+    Word,
+    /// The pattern is a regex
+    Regex,
+}
+
+
+impl MatchKind {
+    fn as_str(self) -> &'static str {
+        match self {
+            MatchKind::Literal => "literal",
+            MatchKind::Word => "word",
+            MatchKind::Regex => "regex",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "literal" => Some(MatchKind::Literal),
+            "word" => Some(MatchKind::Word),
+            "regex" => Some(MatchKind::Regex),
+            _ => None,
+        }
+    }
+}
+
+
+/// Translate the pattern of a responder into a regex
+fn build_pattern(kind: MatchKind, pattern: &str, case_sensitive: bool) -> String {
+    let body = match kind {
+        MatchKind::Literal => regex::escape(pattern),
+        MatchKind::Word => format!(r"(?:^|\W){}(?:\W|$)", regex::escape(pattern)),
+        MatchKind::Regex => format!("(?:{})", pattern),
+    };
+
+    if case_sensitive {
+        body
+    } else {
+        format!("(?i){}", body)
+    }
+}
+
+
+fn compile_pattern(kind: MatchKind, pattern: &str, case_sensitive: bool) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(&build_pattern(kind, pattern, case_sensitive))
+        .size_limit(REGEX_SIZE_LIMIT)
+        .build()
+}
+
+
+/// The auto responders of a guild along with their compiled patterns
+pub struct GuildResponders {
+    set: RegexSet,
+    responders: Vec<AutoResponder>,
+}
+
+
+impl GuildResponders {
+    fn new(responders: Vec<AutoResponder>) -> Self {
+        // responders are validated when they are added, but skip any that no longer compile
+        let responders: Vec<_> = responders
+            .into_iter()
+            .filter(|r| MatchKind::from_name(&r.match_kind)
+                    .map_or(false, |k| compile_pattern(k, &r.pattern, r.case_sensitive).is_ok()))
+            .collect();
+
+        let patterns = responders
+            .iter()
+            .map(|r| build_pattern(MatchKind::from_name(&r.match_kind).unwrap(), &r.pattern, r.case_sensitive));
+
+        let set = RegexSetBuilder::new(patterns)
+            .size_limit(REGEX_SIZE_LIMIT * responders.len().max(1))
+            .build()
+            .unwrap_or_else(|e| {
+                error!(target: "bot", "Couldn't compile auto responders: {}", e);
+                RegexSet::empty()
+            });
+
+        GuildResponders { set, responders }
+    }
+
+    /// Find the first responder that matches a message in a channel and isn't on cooldown
+    fn find_match<F>(&self, content: &str, c_id: i64, mut on_cooldown: F) -> Option<&AutoResponder>
+        where F: FnMut(&AutoResponder) -> bool
+    {
+        self.set
+            .matches(content)
+            .into_iter()
+            .map(|i| &self.responders[i])
+            .filter(|r| r.channel_ids.is_empty() || r.channel_ids.contains(&c_id))
+            .find(|r| !on_cooldown(r))
+    }
+}
+
+
+/// The options and text given when adding a responder
+#[derive(Debug, PartialEq)]
+struct ResponderOptions<'a> {
+    kind: MatchKind,
+    case_sensitive: bool,
+    cooldown: i32,
+    channels: Vec<u64>,
+    pattern: &'a str,
+    response: &'a str,
+}
+
+
+/// Parse `[literal|word|regex] [case] [cooldown {seconds}] [{channels}] {pattern} {response}`,
+/// the pattern can be quoted to include spaces.
+fn parse_responder(text: &str) -> Result<ResponderOptions, &'static str> {
+    lazy_static! {
+        static ref CHANNEL_RE: Regex = Regex::new(r"^<#(?P<id>\d+)>$").unwrap();
+    }
+
+    let mut options = ResponderOptions {
+        kind: MatchKind::Literal,
+        case_sensitive: false,
+        cooldown: 0,
+        channels: Vec::new(),
+        pattern: "",
+        response: "",
+    };
+
+    let mut text = text.trim_start();
+
+    loop {
+        let (word, rest) = next_word(text);
+
+        if let Some(kind) = MatchKind::from_name(&word.to_lowercase()) {
+            options.kind = kind;
+        } else if word.eq_ignore_ascii_case("case") {
+            options.case_sensitive = true;
+        } else if word.eq_ignore_ascii_case("cooldown") {
+            let (seconds, after) = next_word(rest);
+
+            options.cooldown = seconds
+                .parse()
+                .ok()
+                .filter(|s| *s >= 0 && *s <= MAX_COOLDOWN)
+                .ok_or("The cooldown must be a number of seconds, up to a day.")?;
+
+            text = after.trim_start();
+            continue;
+        } else if let Some(caps) = CHANNEL_RE.captures(word) {
+            options.channels.push(caps["id"].parse().map_err(|_| "That channel is not valid.")?);
+        } else {
+            break;
+        }
+
+        text = rest.trim_start();
+    }
+
+    let (pattern, rest) = if text.starts_with('"') {
+        let end = text[1..].find('"').ok_or("The pattern is missing its closing quote.")? + 1;
+        (&text[1..end], &text[end + 1..])
+    } else {
+        next_word(text)
+    };
+
+    options.pattern = pattern;
+    options.response = rest.trim();
+
+    if options.pattern.is_empty() {
+        return Err("Auto responders need a pattern to match.");
+    }
+
+    if options.response.is_empty() {
+        return Err("Auto responders need a response.");
+    }
+
+    Ok(options)
+}
+
+
+/// Get the compiled auto responders of a guild, loading them if they aren't cached
+fn get_guild_responders(ctx: &Context, g_id: GuildId) -> Arc<GuildResponders> {
+    use schema::auto_responder::dsl::*;
+
+    {
+        let mut data = ctx.data.lock();
+        let cache = data.get_mut::<ResponderCache>().unwrap();
+        if let Some(responders) = cache.get_mut(&g_id) {
+            return responders.clone();
+        }
+    }
+
+    let loaded = with_pool(&ctx, |pool| auto_responder
+                           .filter(guild_id.eq(g_id.0 as i64))
+                           .order(id)
+                           .load(&pool)
+                           .expect("Couldn't load auto responders"));
+
+    let responders = Arc::new(GuildResponders::new(loaded));
+
+    let mut data = ctx.data.lock();
+    let cache = data.get_mut::<ResponderCache>().unwrap();
+    cache.insert(g_id, responders.clone());
+
+    responders
+}
+
+
+fn invalidate_responders(ctx: &Context, g_id: GuildId) {
+    let mut data = ctx.data.lock();
+    let cache = data.get_mut::<ResponderCache>().unwrap();
+    cache.remove(&g_id);
+}
+
+
+/// Respond to a message if it matches one of the guild's auto responders
+pub fn process_responders(ctx: &Context, msg: &Message) {
+    let g_id = match msg.guild_id {
+        Some(id) => id,
+        None => return,
+    };
+
+    let responders = get_guild_responders(&ctx, g_id);
+
+    let response = {
+        let now = Instant::now();
+        let mut data = ctx.data.lock();
+        let cooldowns = data.get_mut::<ResponderCooldowns>().unwrap();
+
+        let found = responders.find_match(&msg.content, msg.channel_id.0 as i64, |r| {
+            let cooldown = Duration::from_secs(r.cooldown_seconds as u64);
+            cooldowns.get_mut(&r.id).map_or(false, |last| now.duration_since(*last) < cooldown)
+        });
+
+        match found {
+            Some(r) => {
+                if r.cooldown_seconds > 0 {
+                    cooldowns.insert(r.id, now);
+                }
+
+                r.response.clone()
+            },
+            None => return,
+        }
+    };
+
+    let args: Vec<_> = msg.content.split_whitespace().map(String::from).collect();
+    let text = render_tag(msg, &response, &args);
+
+    if !text.is_empty() {
+        void!(say(msg.channel_id, text));
+    }
+}
+
+
+fn count_responders(ctx: &Context, g_id: i64) -> i64 {
+    use schema::auto_responder::dsl::*;
+
+    with_pool(&ctx, |pool| auto_responder
+              .filter(guild_id.eq(g_id))
+              .count()
+              .get_result(&pool)
+              .unwrap())
+}
+
+
+fn insert_responder(ctx: &Context, responder: &NewAutoResponder) {
+    use schema::auto_responder;
+
+    with_pool(&ctx, |pool| diesel::insert_into(auto_responder::table)
+              .values(responder)
+              .execute(&pool)
+              .expect("Could not insert auto responder"));
+}
+
+
+fn list_responders(ctx: &Context, g_id: i64, page: i64)
+                   -> PaginationResult<(String, bool, i32, Vec<i64>, String, String)> {
+    use schema::auto_responder::dsl::*;
+
+    with_pool(&ctx, |pool| auto_responder
+              .filter(guild_id.eq(g_id))
+              .order(id)
+              .select((match_kind, case_sensitive, cooldown_seconds, channel_ids, pattern, response))
+              .paginate(page)
+              .load_and_count_pages(&pool)
+              .unwrap())
+}
+
+
+fn get_responder_by_index(ctx: &Context, g_id: i64, idx: i64) -> Option<AutoResponder> {
+    use schema::auto_responder::dsl::*;
+
+    // same ordering as `list_responders`
+    with_pool(&ctx, |pool| auto_responder
+              .filter(guild_id.eq(g_id))
+              .order(id)
+              .offset(idx - 1)
+              .first(&pool)
+              .ok())
+}
+
+
+fn delete_responder(ctx: &Context, r_id: i64) {
+    use schema::auto_responder::dsl::*;
+
+    with_pool(&ctx, |pool| diesel::delete(auto_responder.find(r_id))
+              .execute(&pool)
+              .unwrap());
+}
+
+
+command!(responder_add_cmd(ctx, msg, args) {
+    let g_id = msg.guild_id.unwrap();
+    let options = parse_responder(args.full())?;
+
+    if let Err(e) = compile_pattern(options.kind, options.pattern, options.case_sensitive) {
+        return Err(match e {
+            regex::Error::CompiledTooBig(_) => "That pattern is too large.".to_owned(),
+            e => format!("That pattern is not a valid regex: {}", e),
+        }.into());
+    }
+
+    for &c_id in &options.channels {
+        let in_guild = log_time!(with_cache(
+            |cache| cache.guild_channel(ChannelId(c_id)).map_or(false, |c| c.read().guild_id == g_id)
+        ), "with_cache: responder channel in guild");
+
+        if !in_guild {
+            return Err("That channel is not in this server.".into());
+        }
+    }
+
+    if count_responders(&ctx, g_id.0 as i64) >= MAX_RESPONDERS {
+        return Err(format!("Servers can have at most {} auto responders.", MAX_RESPONDERS).into());
+    }
+
+    let channels: Vec<i64> = options.channels.iter().map(|&c| c as i64).collect();
+
+    insert_responder(&ctx, &NewAutoResponder {
+        guild_id: g_id.0 as i64,
+        author_id: msg.author.id.0 as i64,
+        pattern: options.pattern,
+        match_kind: options.kind.as_str(),
+        case_sensitive: options.case_sensitive,
+        response: options.response,
+        channel_ids: &channels,
+        cooldown_seconds: options.cooldown,
+    });
+
+    invalidate_responders(&ctx, g_id);
+
+    void!(say(msg.channel_id, format!("Added a {} auto responder for: {}", options.kind.as_str(), options.pattern)));
+});
+
+
+command!(responder_list_cmd(ctx, msg, args) {
+    let page = args.single::<i64>().unwrap_or(1);
+
+    if page <= 0 {
+        return Err("That page does not exist.".into());
+    }
+
+    let responders = list_responders(&ctx, msg.guild_id.unwrap().0 as i64, page);
+
+    if !responders.page_exists() {
+        return Err("That page does not exist or this server has no auto responders.".into());
+    }
+
+    let block = responders.block(|(ref k, ref c, ref cd, ref chans, ref p, ref r), i| {
+        let preview: String = r.chars().take(30).collect();
+        let case = if *c { "case" } else { "" };
+
+        let channels = if chans.is_empty() {
+            "everywhere".to_owned()
+        } else {
+            chans.iter().map(|&c| channel_label(c)).collect::<Vec<_>>().join(", ")
+        };
+
+        format!("{:>3} | {:<7} | {:<4} | {:>5}s | {} | {} -> {}", i, k, case, cd, channels, p, preview)
+    });
+
+    let message = MessageBuilder::new()
+        .push_line("Auto responders:")
+        .push(block);
+
+    void!(say(msg.channel_id, message));
+});
+
+
+command!(responder_delete_cmd(ctx, msg, args) {
+    let index = get_arg!(args, single, usize, index) as i64;
+    let g_id = msg.guild_id.unwrap();
+
+    let responder = get_responder_by_index(&ctx, g_id.0 as i64, index)
+        .ok_or("That auto responder doesn't exist.")?;
+
+    delete_responder(&ctx, responder.id);
+    invalidate_responders(&ctx, g_id);
+
+    void!(say(msg.channel_id, format!("Deleted the auto responder for: {}", responder.pattern)));
+});
+
+
+pub fn setup_responders(client: &mut Client, frame: StandardFramework) -> StandardFramework {
+    frame.group("Responders",
+                register(client, |g| g
+                .guild_only(true)
+                .command("responder_add", |c| c
+                         .cmd(responder_add_cmd)
+                         .desc(concat!(
+                             "Add an automatic response to messages matching a pattern. ",
+                             "Patterns match anywhere in a message by default, use `word` to match whole words or `regex` for a regex. ",
+                             "Matching ignores case unless `case` is given, `cooldown {seconds}` limits how often it responds ",
+                             "and channel mentions limit it to those channels. Quote the pattern if it has spaces, ",
+                             "the response can use the same templates as tags."))
+                         .example("word cooldown 60 #general \"good morning\" Good morning {user}!")
+                         .usage("[literal|word|regex] [case] [cooldown {seconds}] [{channels}] {pattern} {response}")
+                         .batch_known_as(&["add_responder"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command("responder_list", |c| c
+                         .cmd(responder_list_cmd)
+                         .desc("List the auto responders in this server.")
+                         .usage("{page}")
+                         .batch_known_as(&["responders", "list_responders"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command("responder_delete", |c| c
+                         .cmd(responder_delete_cmd)
+                         .desc("Delete an auto responder by index.")
+                         .usage("{index}")
+                         .batch_known_as(&["delete_responder"])
+                         .required_permissions(Permissions::ADMINISTRATOR)
+                )
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn responder(id: i64, kind: MatchKind, pattern: &str, case_sensitive: bool, channel_ids: Vec<i64>) -> AutoResponder {
+        AutoResponder {
+            id,
+            guild_id: 1,
+            author_id: 1,
+            pattern: pattern.to_owned(),
+            match_kind: kind.as_str().to_owned(),
+            case_sensitive,
+            response: "hi".to_owned(),
+            channel_ids,
+            cooldown_seconds: 0,
+        }
+    }
+
+    #[test]
+    fn test_parse_responder() {
+        let options = parse_responder("word case cooldown 30 <#123> \"good morning\" hi {user}").unwrap();
+
+        assert_eq!(options, ResponderOptions {
+            kind: MatchKind::Word,
+            case_sensitive: true,
+            cooldown: 30,
+            channels: vec![123],
+            pattern: "good morning",
+            response: "hi {user}",
+        });
+
+        let options = parse_responder("ping pong").unwrap();
+        assert_eq!((options.kind, options.pattern, options.response), (MatchKind::Literal, "ping", "pong"));
+
+        assert!(parse_responder("regex \"unterminated hi").is_err());
+        assert!(parse_responder("cooldown forever ping pong").is_err());
+        assert!(parse_responder("ping").is_err());
+    }
+
+    #[test]
+    fn test_find_match() {
+        let responders = GuildResponders::new(vec![
+            responder(1, MatchKind::Word, "cat", false, vec![]),
+            responder(2, MatchKind::Literal, "Dog", true, vec![]),
+            responder(3, MatchKind::Regex, r"^\d+$", false, vec![10]),
+            responder(4, MatchKind::Regex, r"(", false, vec![]),
+        ]);
+
+        let find = |content, c_id| responders.find_match(content, c_id, |_| false).map(|r| r.id);
+
+        assert_eq!(find("my CAT!", 5), Some(1));
+        assert_eq!(find("concatenate", 5), None);
+        assert_eq!(find("hotDogs", 5), Some(2));
+        assert_eq!(find("hotdogs", 5), None);
+        assert_eq!(find("1234", 5), None);
+        assert_eq!(find("1234", 10), Some(3));
+        assert_eq!(responders.find_match("cat Dog", 5, |r| r.id == 1).map(|r| r.id), Some(2));
+    }
+}
@@ -1,10 +1,13 @@
 use serenity::{
     prelude::*,
+    builder::{CreateEmbed, CreateMessage},
     model::{
         channel::Message,
+        id::{GuildId, RoleId, UserId},
         permissions::Permissions,
     },
     framework::standard::{
+        Args,
         StandardFramework,
         CommandError,
     },
@@ -16,10 +19,14 @@ use serenity::{
 use diesel::prelude::*;
 use diesel;
 use ::PgConnectionManager;
-use models::Tag;
+use models::{NewTag, Tag, TagAttachment, TagRevision};
 use itertools::Itertools;
+use regex::Regex;
+use chrono::{NaiveDateTime, Utc};
+use commands::register;
 use utils::{
     say,
+    send_message,
     pagination::{
         PaginationResult,
         Paginate,
@@ -27,147 +34,1631 @@ use utils::{
 };
 
 
+/// Where a tag can be used, tags are looked up in this order
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum TagScope {
+    Guild,
+    Personal,
+    Global,
+}
+
+
+impl TagScope {
+    fn as_str(self) -> &'static str {
+        match self {
+            TagScope::Guild => "guild",
+            TagScope::Personal => "personal",
+            TagScope::Global => "global",
+        }
+    }
+
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "guild" | "server" => Some(TagScope::Guild),
+            "personal" => Some(TagScope::Personal),
+            "global" => Some(TagScope::Global),
+            _ => None,
+        }
+    }
+}
+
+
+/// Get the row for a key, which might be an alias
+fn get_tag_row(ctx: &Context, g_id: i64, tag_key: &str) -> QueryResult<Tag> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    tag.filter(guild_id.eq(&g_id))
+       .filter(key.eq(tag_key))
+       .first(pool)
+}
+
+
+/// Follow an alias to the tag it points to
+fn resolve_alias(ctx: &Context, t: Tag) -> QueryResult<Tag> {
+    use schema::tag::dsl::*;
+
+    match t.alias_of {
+        Some(t_id) => {
+            let pool = extract_pool!(&ctx);
+            tag.find(t_id).first(pool)
+        },
+        None => Ok(t),
+    }
+}
+
+
+/// Get a guild tag by key, resolving aliases
 fn get_tag(ctx: &Context, g_id: i64, tag_key: &str) -> QueryResult<Tag> {
+    let t = get_tag_row(&ctx, g_id, tag_key)?;
+    resolve_alias(&ctx, t)
+}
+
+
+/// Get a tag, or a message suggesting similar tags if it doesn't exist
+fn get_tag_or_suggest(ctx: &Context, g_id: i64, tag_key: &str) -> Result<Tag, String> {
+    get_tag(&ctx, g_id, tag_key).map_err(|_| missing_tag_message(&ctx, g_id, tag_key))
+}
+
+
+/// Get the personal tag of a user or a global tag
+fn get_unscoped_tag(ctx: &Context, s: TagScope, u_id: i64, tag_key: &str) -> QueryResult<Tag> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    match s {
+        TagScope::Personal => tag
+            .filter(scope.eq(s.as_str()))
+            .filter(author_id.eq(u_id))
+            .filter(key.eq(tag_key))
+            .first(pool),
+        _ => tag
+            .filter(scope.eq(s.as_str()))
+            .filter(key.eq(tag_key))
+            .first(pool),
+    }
+}
+
+
+/// All the tags with one of the keys that a user can use in a guild or DM
+fn visible_tags(ctx: &Context, g_id: Option<i64>, u_id: i64, keys: &[String]) -> Vec<(Tag, TagScope)> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    let found: Vec<Tag> = tag
+        .filter(key.eq_any(keys))
+        .filter(scope.eq("guild").and(guild_id.eq(g_id))
+                .or(scope.eq("personal").and(author_id.eq(u_id)))
+                .or(scope.eq("global")))
+        .load(pool)
+        .unwrap_or_default();
+
+    found.into_iter()
+        .filter_map(|t| TagScope::from_name(&t.scope).map(|s| (t, s)))
+        .collect()
+}
+
+
+/// Find a tag by key, checking the guild's tags, then personal tags, then global tags
+pub fn find_tag(ctx: &Context, g_id: Option<i64>, u_id: i64, tag_key: &str) -> Option<Tag> {
+    let (found, _) = visible_tags(&ctx, g_id, u_id, &[tag_key.to_owned()])
+        .into_iter()
+        .min_by_key(|&(_, s)| s)?;
+
+    resolve_alias(&ctx, found).ok()
+}
+
+
+/// Find the tag with the longest key made from the leading words, returns the tag and the number of words used.
+fn get_tag_with_args(ctx: &Context, g_id: Option<i64>, u_id: i64, words: &[String]) -> Option<(Tag, usize)> {
+    use std::cmp::Reverse;
+
+    let keys: Vec<_> = (1..=words.len()).map(|n| words[..n].join(" ")).collect();
+
+    // the longest key wins, then the closest scope
+    let (found, n) = visible_tags(&ctx, g_id, u_id, &keys)
+        .into_iter()
+        .filter_map(|(t, s)| keys.iter().position(|k| *k == t.key).map(|n| (t, s, n + 1)))
+        .max_by_key(|&(_, s, n)| (n, Reverse(s)))
+        .map(|(t, _, n)| (t, n))?;
+
+    resolve_alias(&ctx, found).ok().map(|t| (t, n))
+}
+
+
+/// Render the template of a tag invoked by a message
+pub fn render_tag(msg: &Message, template: &str, args: &[String]) -> String {
+    use rand::thread_rng;
+    use utils::template::{render, TemplateVars};
+
+    let guild_name = msg.guild_id
+        .and_then(|g| g.to_guild_cached())
+        .map(|g| g.read().name.clone())
+        .unwrap_or_default();
+
+    let channel_name = msg.channel_id.name().unwrap_or_default();
+
+    let vars = TemplateVars {
+        user: &msg.author.name,
+        user_id: msg.author.id.0,
+        channel: &channel_name,
+        guild: &guild_name,
+        args,
+    };
+
+    render(template, &vars, &mut thread_rng())
+}
+
+
+/// Discord's length limits for the parts of an embed, in characters
+const EMBED_TITLE_LIMIT: usize = 256;
+const EMBED_DESCRIPTION_LIMIT: usize = 2048;
+const EMBED_FIELD_NAME_LIMIT: usize = 256;
+const EMBED_FIELD_VALUE_LIMIT: usize = 1024;
+const EMBED_TOTAL_LIMIT: usize = 6000;
+
+/// Length of the column embeds are stored in
+const EMBED_STORED_LIMIT: usize = 8000;
+
+
+/// An embed sent along with a tag
+struct TagEmbed {
+    title: Option<String>,
+    description: Option<String>,
+    colour: Option<u32>,
+    fields: Vec<(String, String, bool)>,
+}
+
+
+/// Parse and validate the JSON definition of a tag embed, for example:
+/// `{"title": "..", "description": "..", "colour": "#ff0000", "fields": [{"name": "..", "value": "..", "inline": true}]}`
+fn parse_tag_embed(json: &str) -> Result<TagEmbed, &'static str> {
+    use serde_json::Value;
+
+    fn string(value: Option<&Value>, limit: usize) -> Result<Option<String>, &'static str> {
+        match value {
+            None | Some(Value::Null) => Ok(None),
+            Some(Value::String(s)) if s.chars().count() > limit => Err("A part of the embed is too long."),
+            Some(Value::String(s)) => Ok(Some(s.clone())),
+            _ => Err("Embed titles, descriptions, names and values must be strings."),
+        }
+    }
+
+    let parsed: Value = serde_json::from_str(json).map_err(|_| "The embed is not valid JSON.")?;
+    let obj = parsed.as_object().ok_or("The embed must be a JSON object.")?;
+
+    let title = string(obj.get("title"), EMBED_TITLE_LIMIT)?;
+    let description = string(obj.get("description"), EMBED_DESCRIPTION_LIMIT)?;
+
+    let colour = match obj.get("colour").or_else(|| obj.get("color")) {
+        None | Some(Value::Null) => None,
+        Some(Value::Number(n)) => Some(n.as_u64().filter(|&n| n <= 0xFF_FFFF).ok_or("Embed colours must be between 0 and 0xFFFFFF.")? as u32),
+        Some(Value::String(s)) => Some(u32::from_str_radix(s.trim_start_matches('#'), 16)
+                                       .ok()
+                                       .filter(|&n| n <= 0xFF_FFFF)
+                                       .ok_or("Embed colours must look like #ff0000.")?),
+        _ => return Err("Embed colours must be a number or a string like #ff0000."),
+    };
+
+    let fields = match obj.get("fields") {
+        None | Some(Value::Null) => Vec::new(),
+        Some(Value::Array(fields)) if fields.len() > 25 => return Err("Embeds can have at most 25 fields."),
+        Some(Value::Array(fields)) => fields
+            .iter()
+            .map(|f| {
+                let name = string(f.get("name"), EMBED_FIELD_NAME_LIMIT)?.ok_or("Embed fields need a name.")?;
+                let value = string(f.get("value"), EMBED_FIELD_VALUE_LIMIT)?.ok_or("Embed fields need a value.")?;
+                let inline = f.get("inline").and_then(Value::as_bool).unwrap_or(false);
+                Ok((name, value, inline))
+            })
+            .collect::<Result<_, &'static str>>()?,
+        _ => return Err("Embed fields must be a list."),
+    };
+
+    if title.is_none() && description.is_none() && fields.is_empty() {
+        return Err("The embed needs a title, description or fields.");
+    }
+
+    let embed = TagEmbed { title, description, colour, fields };
+
+    if embed.len() > EMBED_TOTAL_LIMIT {
+        return Err("Embeds can have at most 6000 characters in total.");
+    }
+
+    Ok(embed)
+}
+
+
+impl TagEmbed {
+    /// The number of characters Discord counts towards the total length of the embed
+    fn len(&self) -> usize {
+        let count = |s: &Option<String>| s.as_ref().map_or(0, |s| s.chars().count());
+
+        count(&self.title) + count(&self.description) + self.fields
+            .iter()
+            .map(|&(ref n, ref v, _)| n.chars().count() + v.chars().count())
+            .sum::<usize>()
+    }
+
+    /// Render the templates in the embed, cutting the results down to fit the limits of Discord.
+    ///
+    /// Fields that don't fit in the total length are left out.
+    fn render(&self, render: impl Fn(&str) -> String) -> TagEmbed {
+        // names and values can't be empty
+        fn cut(s: String, limit: usize, remaining: &mut usize) -> String {
+            let s: String = s.chars().take(limit.min(*remaining)).collect();
+            let s = if s.trim().is_empty() { "\u{200b}".to_owned() } else { s };
+            *remaining = remaining.saturating_sub(s.chars().count());
+            s
+        }
+
+        let mut remaining = EMBED_TOTAL_LIMIT;
+
+        let title = self.title.as_ref().map(|t| cut(render(t), EMBED_TITLE_LIMIT, &mut remaining));
+        let description = self.description.as_ref().map(|d| cut(render(d), EMBED_DESCRIPTION_LIMIT, &mut remaining));
+
+        let mut fields = Vec::new();
+
+        for &(ref name, ref value, inline) in &self.fields {
+            // leave room for at least one character of the value
+            if remaining < 2 {
+                break;
+            }
+
+            let name = cut(render(name), EMBED_FIELD_NAME_LIMIT.min(remaining - 1), &mut remaining);
+            let value = cut(render(value), EMBED_FIELD_VALUE_LIMIT, &mut remaining);
+
+            fields.push((name, value, inline));
+        }
+
+        TagEmbed { title, description, colour: self.colour, fields }
+    }
+
+    /// The compact JSON definition of the embed, understood by `parse_tag_embed`
+    fn to_json(&self) -> String {
+        let mut obj = serde_json::Map::new();
+
+        if let Some(ref title) = self.title {
+            obj.insert("title".to_owned(), json!(title));
+        }
+
+        if let Some(ref description) = self.description {
+            obj.insert("description".to_owned(), json!(description));
+        }
+
+        if let Some(colour) = self.colour {
+            obj.insert("colour".to_owned(), json!(colour));
+        }
+
+        if !self.fields.is_empty() {
+            let fields: Vec<_> = self.fields
+                .iter()
+                .map(|&(ref name, ref value, inline)| json!({ "name": name, "value": value, "inline": inline }))
+                .collect();
+
+            obj.insert("fields".to_owned(), json!(fields));
+        }
+
+        serde_json::Value::Object(obj).to_string()
+    }
+}
+
+
+/// Build a rendered tag embed
+fn build_tag_embed(e: CreateEmbed, embed: &TagEmbed, image: Option<&str>) -> CreateEmbed {
+    let e = match embed.title {
+        Some(ref t) => e.title(t),
+        None => e,
+    };
+
+    let e = match embed.description {
+        Some(ref d) => e.description(d),
+        None => e,
+    };
+
+    let e = match embed.colour {
+        Some(c) => e.colour(c),
+        None => e,
+    };
+
+    let e = match image {
+        Some(url) => e.image(url),
+        None => e,
+    };
+
+    embed.fields
+        .iter()
+        .fold(e, |e, &(ref name, ref value, inline)| e.field(name, value, inline))
+}
+
+
+fn is_image(filename: &str) -> bool {
+    let filename = filename.to_lowercase();
+
+    [".png", ".jpg", ".jpeg", ".gif", ".webp"]
+        .iter()
+        .any(|ext| filename.ends_with(ext))
+}
+
+
+fn get_attachments(ctx: &Context, t_id: i64) -> Vec<TagAttachment> {
+    use schema::tag_attachment::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    tag_attachment.filter(tag_id.eq(t_id))
+        .order(id.asc())
+        .load(pool)
+        .unwrap_or_default()
+}
+
+
+/// Send a tag in response to a message, along with its embed and attachments
+pub fn send_tag(ctx: &Context, msg: &Message, t: &Tag, args: &[String]) {
+    let render = |s: &str| render_tag(msg, s, args);
+
+    let text = render(&t.text);
+    let embed = t.embed.as_ref().and_then(|e| parse_tag_embed(e).ok()).map(|e| e.render(&render));
+    let attachments = get_attachments(&ctx, t.id);
+
+    // an image attachment is shown inside the embed if the tag has one
+    let image_url = match embed {
+        Some(_) => attachments
+            .iter()
+            .find(|a| is_image(&a.filename))
+            .map(|a| format!("attachment://{}", a.filename)),
+        None => None,
+    };
+
+    let build = |m: CreateMessage| {
+        let m = if text.is_empty() { m } else { m.content(&text) };
+
+        match embed {
+            Some(ref e) => m.embed(|b| build_tag_embed(b, e, image_url.as_ref().map(String::as_str))),
+            None => m,
+        }
+    };
+
+    if attachments.is_empty() {
+        void!(send_message(msg.channel_id, build));
+    } else {
+        let files: Vec<_> = attachments
+            .iter()
+            .map(|a| (a.data.as_slice(), a.filename.as_str()))
+            .collect();
+
+        void!(msg.channel_id.send_files(files, build));
+    }
+}
+
+
+#[derive(QueryableByName)]
+struct TagMatch {
+    #[sql_type = "diesel::sql_types::Varchar"]
+    key: String,
+    #[sql_type = "diesel::sql_types::Varchar"]
+    text: String,
+}
+
+
+/// Search tag keys and content by trigram similarity, best matches first
+fn search_tags(ctx: &Context, g_id: i64, query: &str) -> Vec<TagMatch> {
+    use diesel::sql_types::{BigInt, Varchar};
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::sql_query(r#"
+        SELECT "key", "text" FROM "tag"
+        WHERE "guild_id" = $1 AND "alias_of" IS NULL
+          AND ("key" % $2 OR $2 <% "text" OR strpos(lower("key"), lower($2)) > 0)
+        ORDER BY GREATEST(similarity("key", $2), word_similarity($2, "text")) DESC, "key"
+        LIMIT 10
+   "#)
+        .bind::<BigInt, i64>(g_id)
+        .bind::<Varchar, _>(query)
+        .load(pool)
+        .unwrap()
+}
+
+
+#[derive(QueryableByName)]
+struct TagKey {
+    #[sql_type = "diesel::sql_types::Varchar"]
+    key: String,
+}
+
+
+/// How similar a tag has to be to an unrecognised command to be suggested,
+/// commands for other bots with the same prefix shouldn't get suggestions
+pub const AUTO_SUGGESTION_SIMILARITY: f32 = 0.6;
+
+
+/// The keys of the tags most similar to a key that doesn't exist, at least `min_similarity` similar
+fn similar_tag_keys(ctx: &Context, g_id: i64, tag_key: &str, min_similarity: f32) -> Vec<String> {
+    use diesel::sql_types::{BigInt, Float, Varchar};
+
+    let pool = extract_pool!(&ctx);
+
+    let keys: Vec<TagKey> = diesel::sql_query(r#"
+        SELECT "key" FROM "tag"
+        WHERE "guild_id" = $1 AND "key" % $2 AND similarity("key", $2) >= $3
+        ORDER BY similarity("key", $2) DESC
+        LIMIT 3
+   "#)
+        .bind::<BigInt, i64>(g_id)
+        .bind::<Varchar, _>(tag_key)
+        .bind::<Float, _>(min_similarity)
+        .load(pool)
+        .unwrap_or_default();
+
+    keys.into_iter().map(|k| k.key).collect()
+}
+
+
+/// Suggest the closest tags to a key that doesn't exist, if there are any
+pub fn tag_suggestions(ctx: &Context, g_id: i64, tag_key: &str, min_similarity: f32) -> Option<String> {
+    let similar = similar_tag_keys(&ctx, g_id, tag_key, min_similarity);
+
+    if similar.is_empty() {
+        return None;
+    }
+
+    let content = similar
+        .iter()
+        .enumerate()
+        .fold(MessageBuilder::new().push("That tag does not exist, did you mean: "), |m, (i, k)| {
+            let m = if i > 0 { m.push(", ") } else { m };
+            m.push_mono_safe(k)
+        });
+
+    Some(content.push("?").build())
+}
+
+
+fn missing_tag_message(ctx: &Context, g_id: i64, tag_key: &str) -> String {
+    tag_suggestions(&ctx, g_id, tag_key, 0.0).unwrap_or_else(|| "That tag does not exist.".to_owned())
+}
+
+
+fn insert_tag(ctx: &Context, msg: &Message, scope: TagScope, key: &str, content: &str) -> i64 {
+    let guild_id = match scope {
+        TagScope::Guild => msg.guild_id.map(|g| g.0 as i64),
+        _ => None,
+    };
+
+    let new_tag =  NewTag {
+        author_id: msg.author.id.0 as i64,
+        guild_id,
+        key,
+        text: content,
+        alias_of: None,
+        scope: scope.as_str(),
+    };
+
+    insert_tag_row(&ctx, &new_tag).expect("Couldn't insert tag")
+}
+
+
+/// Insert a tag, failing if the key is already used because of the tag table's unique constraints
+fn insert_tag_row(ctx: &Context, new_tag: &NewTag) -> QueryResult<i64> {
+    use schema::tag;
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::insert_into(tag::table)
+        .values(new_tag)
+        .returning(tag::id)
+        .get_result(pool)
+}
+
+
+fn insert_attachments(ctx: &Context, t_id: i64, files: &[(String, Vec<u8>)]) {
+    use schema::tag_attachment;
+    use models::NewTagAttachment;
+
+    let attachments: Vec<_> = files
+        .iter()
+        .map(|&(ref filename, ref data)| NewTagAttachment {
+            tag_id: t_id,
+            filename,
+            data,
+        })
+        .collect();
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::insert_into(tag_attachment::table)
+        .values(&attachments)
+        .execute(pool)
+        .expect("Couldn't insert tag attachments");
+}
+
+
+fn set_tag_embed(ctx: &Context, t_id: i64, new_embed: Option<&str>) -> QueryResult<()> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::update(tag.find(t_id))
+        .set(embed.eq(new_embed))
+        .execute(pool)
+        .map(|_| ())
+}
+
+
+fn insert_alias(ctx: &Context, author_id: i64, key: &str, target: &Tag) -> QueryResult<i64> {
+    let new_alias = NewTag {
+        author_id,
+        guild_id: target.guild_id,
+        key,
+        text: "",
+        alias_of: Some(target.id),
+        scope: TagScope::Guild.as_str(),
+    };
+
+    insert_tag_row(&ctx, &new_alias)
+}
+
+
+/// The alias keys of some tags, in the same order as the ids
+fn aliases_of(ctx: &Context, t_ids: &[i64]) -> Vec<Vec<String>> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    let aliases: Vec<(Option<i64>, String)> = tag
+        .filter(alias_of.eq_any(t_ids))
+        .order(key.asc())
+        .select((alias_of, key))
+        .load(pool)
+        .unwrap();
+
+    t_ids
+        .iter()
+        .map(|t_id| aliases
+             .iter()
+             .filter(|&&(a, _)| a == Some(*t_id))
+             .map(|&(_, ref k)| k.clone())
+             .collect())
+        .collect()
+}
+
+
+fn delete_tag_do(ctx: &Context, tag_id: i64) {
     use schema::tag::dsl::*;
 
-    let pool = extract_pool!(&ctx);
+    let pool = extract_pool!(&ctx);
+
+    diesel::delete(tag.filter(id.eq(tag_id)))
+        .execute(pool)
+        .unwrap();
+}
+
+
+/// Replace the text of a tag, keeping the old text as a revision
+fn update_tag_text(ctx: &Context, t: &Tag, new_text: &str, editor_id: i64) {
+    use models::NewTagRevision;
+    use schema::{tag, tag_revision};
+
+    let now = Utc::now().naive_utc();
+
+    let revision = NewTagRevision {
+        tag_id: t.id,
+        text: &t.text,
+        replaced_by: editor_id,
+        replaced_at: &now,
+    };
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::insert_into(tag_revision::table)
+        .values(&revision)
+        .execute(pool)
+        .expect("Couldn't insert tag revision");
+
+    diesel::update(tag::table.find(t.id))
+        .set(tag::text.eq(new_text))
+        .execute(pool)
+        .unwrap();
+}
+
+
+fn list_revisions(ctx: &Context, t_id: i64, page: i64) -> PaginationResult<(NaiveDateTime, i64, String)> {
+    use schema::tag_revision::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    tag_revision.filter(tag_id.eq(t_id))
+        .order(id.asc())
+        .select((replaced_at, replaced_by, text))
+        .paginate(page)
+        .load_and_count_pages(pool)
+        .unwrap()
+}
+
+
+fn get_revision(ctx: &Context, t_id: i64, rev: i64) -> Option<TagRevision> {
+    use schema::tag_revision::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    // same ordering as `list_revisions`
+    tag_revision.filter(tag_id.eq(t_id))
+        .order(id.asc())
+        .offset(rev - 1)
+        .first(pool)
+        .ok()
+}
+
+
+/// Only the author of a tag or members with manage messages can change or delete it
+fn can_manage_tag(msg: &Message, t: &Tag) -> bool {
+    if t.author_id as u64 == msg.author.id.0 {
+        return true;
+    }
+
+    log_time!(with_cache(
+        |cache| cache.guild(msg.guild_id.unwrap()).map_or(
+            false,
+            |g| g.read().member_permissions(msg.author.id).manage_messages()
+        )
+    ), "with_cache: has_manage_messages")
+}
+
+
+fn set_tag_author(ctx: &Context, t_id: i64, new_author: i64) {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::update(tag.find(t_id))
+        .set(author_id.eq(new_author))
+        .execute(pool)
+        .unwrap();
+}
+
+
+/// Check if a user is in a guild with the cache `names_for_members` uses,
+/// asking discord if they aren't cached since large guilds aren't fully cached.
+///
+/// Only an unknown member response from discord means they are gone, any other failure is an error.
+fn is_guild_member(g_id: GuildId, u_id: UserId) -> Result<bool, String> {
+    use serenity::http::StatusCode;
+
+    let cached = log_time!(with_cache(
+        |cache| cache.guild(g_id).map_or(false, |g| g.read().members.contains_key(&u_id))
+    ), "with_cache: is_guild_member");
+
+    if cached {
+        return Ok(true);
+    }
+
+    match g_id.member(u_id) {
+        Ok(_) => Ok(true),
+        Err(SerenityError::Http(HttpError::UnsuccessfulRequest(ref resp))) if resp.status == StatusCode::NotFound => Ok(false),
+        Err(e) => Err(format!("Couldn't check who is in this guild, try again later: {}", e)),
+    }
+}
+
+
+/// Limits on creating guild tags, `None` when there is no limit
+struct TagLimits {
+    per_user: Option<i32>,
+    per_guild: Option<i32>,
+    create_role: Option<i64>,
+}
+
+
+fn get_tag_limits(ctx: &Context, g_id: i64) -> TagLimits {
+    use schema::guild::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    let (per_user, per_guild, create_role) = guild
+        .find(g_id)
+        .select((tag_max_per_user, tag_max_per_guild, tag_create_role))
+        .first(pool)
+        .unwrap_or((None, None, None));
+
+    TagLimits { per_user, per_guild, create_role }
+}
+
+
+fn set_tag_limits(ctx: &Context, g_id: i64, limits: &TagLimits) {
+    use schema::guild::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::update(guild.find(g_id))
+        .set((tag_max_per_user.eq(limits.per_user),
+              tag_max_per_guild.eq(limits.per_guild),
+              tag_create_role.eq(limits.create_role)))
+        .execute(pool)
+        .unwrap();
+}
+
+
+/// Count the tags in a guild, or the tags a user made in a guild, leaving out aliases
+fn count_guild_tags(ctx: &Context, g_id: i64, author: Option<i64>) -> i64 {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    let mut query = tag
+        .filter(guild_id.eq(g_id))
+        .filter(alias_of.is_null())
+        .into_boxed();
+
+    if let Some(a_id) = author {
+        query = query.filter(author_id.eq(a_id));
+    }
+
+    query.count().get_result(pool).unwrap()
+}
+
+
+/// Check the tag limits of the guild allow someone to create a tag,
+/// members with manage messages are only held to the guild limit
+fn check_tag_limits(ctx: &Context, msg: &Message) -> Result<(), String> {
+    let g_id = msg.guild_id.unwrap();
+    let limits = get_tag_limits(&ctx, g_id.0 as i64);
+
+    if let Some(max) = limits.per_guild {
+        if count_guild_tags(&ctx, g_id.0 as i64, None) >= i64::from(max) {
+            return Err(format!("This server has reached its limit of {} tags.", max));
+        }
+    }
+
+    let (is_moderator, has_role) = log_time!(with_cache(
+        |cache| cache.guild(g_id).map_or((false, false), |g| {
+            let g = g.read();
+            let has_role = limits.create_role.map_or(true, |r| g.members
+                                                     .get(&msg.author.id)
+                                                     .map_or(false, |m| m.roles.contains(&RoleId(r as u64))));
+            (g.member_permissions(msg.author.id).manage_messages(), has_role)
+        })
+    ), "with_cache: check_tag_limits");
+
+    if is_moderator {
+        return Ok(());
+    }
+
+    if !has_role {
+        let role = limits.create_role.map(role_label).unwrap_or_default();
+        return Err(format!("You need the role {} to create tags in this server.", role));
+    }
+
+    if let Some(max) = limits.per_user {
+        if count_guild_tags(&ctx, g_id.0 as i64, Some(msg.author.id.0 as i64)) >= i64::from(max) {
+            return Err(format!("You have reached this server's limit of {} tags per member.", max));
+        }
+    }
+
+    Ok(())
+}
+
+
+/// The name of a role without mentioning it
+fn role_label(r_id: i64) -> String {
+    RoleId(r_id as u64)
+        .to_role_cached()
+        .map(|r| r.name)
+        .unwrap_or_else(|| r_id.to_string())
+}
+
+
+/// Parse a tag limit, `none` or `off` remove the limit
+fn parse_tag_limit(s: &str) -> Result<Option<i32>, &'static str> {
+    const MAX_LIMIT: i32 = 100_000;
+
+    match s.to_lowercase().as_str() {
+        "none" | "off" => Ok(None),
+        s => s.parse::<i32>()
+            .ok()
+            .filter(|n| *n >= 0 && *n <= MAX_LIMIT)
+            .map(Some)
+            .ok_or("Limits must be a number up to 100000, or `none` to remove the limit."),
+    }
+}
+
+
+/// Count a use of a tag
+pub fn record_tag_use(ctx: &Context, t_id: i64) {
+    use schema::tag::dsl::*;
+
+    let now = Utc::now().naive_utc();
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::update(tag.find(t_id))
+        .set((uses.eq(uses + 1), last_used.eq(now)))
+        .execute(pool)
+        .unwrap();
+}
+
+
+fn top_tags(ctx: &Context, g_id: i64, page: i64) -> PaginationResult<(String, i32, Option<NaiveDateTime>)> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    tag.filter(guild_id.eq(&g_id))
+       .filter(alias_of.is_null())
+       .order((uses.desc(), key.asc()))
+       .select((key, uses, last_used))
+       .paginate(page)
+       .load_and_count_pages(pool)
+       .unwrap()
+}
+
+
+/// A guild tag as it appears in an export
+#[derive(Debug, PartialEq)]
+struct ExportedTag {
+    key: String,
+    content: String,
+    author_id: u64,
+    created_at: Option<NaiveDateTime>,
+    last_used: Option<NaiveDateTime>,
+    uses: i32,
+    embed: Option<String>,
+    aliases: Vec<String>,
+}
+
+
+const EXPORT_TIME_FORMAT: &str = "%Y-%m-%dT%H:%M:%S";
+
+
+fn write_tags_export(tags: &[ExportedTag]) -> String {
+    let format_time = |t: &Option<NaiveDateTime>| t.map(|t| t.format(EXPORT_TIME_FORMAT).to_string());
+
+    let tags: Vec<_> = tags
+        .iter()
+        .map(|t| json!({
+            "key": t.key,
+            "content": t.content,
+            "author_id": t.author_id,
+            "created_at": format_time(&t.created_at),
+            "last_used": format_time(&t.last_used),
+            "uses": t.uses,
+            "embed": t.embed,
+            "aliases": t.aliases,
+        }))
+        .collect();
+
+    serde_json::to_string_pretty(&json!({ "version": 1, "tags": tags })).unwrap()
+}
+
+
+fn parse_tags_export(json: &str) -> Result<Vec<ExportedTag>, &'static str> {
+    use serde_json::Value;
+
+    let parsed: Value = serde_json::from_str(json).map_err(|_| "That file is not valid JSON.")?;
+
+    let tags = parsed
+        .get("tags")
+        .and_then(Value::as_array)
+        .ok_or("That file is not a tag export.")?;
+
+    let parse_time = |v: Option<&Value>| v
+        .and_then(Value::as_str)
+        .and_then(|t| NaiveDateTime::parse_from_str(t, EXPORT_TIME_FORMAT).ok());
+
+    tags.iter()
+        .map(|t| {
+            let key = t.get("key").and_then(Value::as_str).ok_or("A tag in the export has no key.")?;
+            let content = t.get("content").and_then(Value::as_str).unwrap_or("");
+
+            if key.is_empty() || key.len() >= 50 || content.chars().count() > 2000 {
+                return Err("A tag in the export has a key or content that is too long.");
+            }
+
+            // stored compactly, so padding can't push it past the length of the column
+            let embed = match t.get("embed").and_then(Value::as_str) {
+                Some(e) => {
+                    let e = parse_tag_embed(e)?.to_json();
+
+                    if e.chars().count() > EMBED_STORED_LIMIT {
+                        return Err("A tag in the export has an embed that is too long.");
+                    }
+
+                    Some(e)
+                },
+                None => None,
+            };
+
+            let aliases = t.get("aliases")
+                .and_then(Value::as_array)
+                .map(|a| a.iter()
+                     .filter_map(Value::as_str)
+                     .filter(|a| !a.is_empty() && a.len() < 50)
+                     .map(str::to_owned)
+                     .collect())
+                .unwrap_or_default();
+
+            Ok(ExportedTag {
+                key: key.to_owned(),
+                content: content.to_owned(),
+                author_id: t.get("author_id").and_then(Value::as_u64).unwrap_or(0),
+                created_at: parse_time(t.get("created_at")),
+                last_used: parse_time(t.get("last_used")),
+                uses: t.get("uses").and_then(Value::as_i64).unwrap_or(0).max(0).min(i64::from(std::i32::MAX)) as i32,
+                embed,
+                aliases,
+            })
+        })
+        .collect()
+}
+
+
+fn export_tags(ctx: &Context, g_id: i64) -> Vec<ExportedTag> {
+    use schema::tag::dsl::*;
+
+    let tags: Vec<Tag> = {
+        let pool = extract_pool!(&ctx);
+
+        tag.filter(guild_id.eq(&g_id))
+            .filter(alias_of.is_null())
+            .order(key.asc())
+            .load(pool)
+            .unwrap()
+    };
+
+    let tag_ids: Vec<i64> = tags.iter().map(|t| t.id).collect();
+    let aliases = aliases_of(&ctx, &tag_ids);
+
+    tags.into_iter()
+        .zip(aliases)
+        .map(|(t, aliases)| ExportedTag {
+            key: t.key,
+            content: t.text,
+            author_id: t.author_id as u64,
+            created_at: t.created_at,
+            last_used: t.last_used,
+            uses: t.uses,
+            embed: t.embed,
+            aliases,
+        })
+        .collect()
+}
+
+
+/// Copy the usage, timestamps and embed of an imported tag
+fn set_imported_fields(ctx: &Context, t_id: i64, imported: &ExportedTag) -> QueryResult<()> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::update(tag.find(t_id))
+        .set((created_at.eq(imported.created_at.unwrap_or_else(|| Utc::now().naive_utc())),
+              last_used.eq(imported.last_used),
+              uses.eq(imported.uses),
+              embed.eq(&imported.embed)))
+        .execute(pool)
+        .map(|_| ())
+}
+
+
+/// What to do when an imported tag has the same key as an existing tag
+#[derive(Clone, Copy, PartialEq)]
+enum ConflictPolicy {
+    Skip,
+    Overwrite,
+    Rename,
+}
+
+
+#[derive(Default)]
+struct ImportCounts {
+    created: usize,
+    skipped: usize,
+    overwritten: usize,
+    renamed: usize,
+    skipped_aliases: Vec<String>,
+}
+
+
+/// Import a tag into a guild, relying on the unique constraint to find conflicts
+fn import_tag(ctx: &Context, msg: &Message, g_id: i64, imported: &ExportedTag,
+              policy: ConflictPolicy, counts: &mut ImportCounts) {
+    use diesel::result::{DatabaseErrorKind, Error};
+
+    let is_conflict = |r: &QueryResult<i64>| match *r {
+        Err(Error::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => true,
+        _ => false,
+    };
+
+    let insert = |key: &str| insert_tag_row(&ctx, &NewTag {
+        author_id: imported.author_id as i64,
+        guild_id: Some(g_id),
+        key,
+        text: &imported.content,
+        alias_of: None,
+        scope: TagScope::Guild.as_str(),
+    });
+
+    let mut result = insert(&imported.key);
+
+    if is_conflict(&result) {
+        match policy {
+            ConflictPolicy::Skip => {
+                counts.skipped += 1;
+                return;
+            },
+            ConflictPolicy::Overwrite => {
+                let existing = match get_tag_row(&ctx, g_id, &imported.key) {
+                    Ok(t) => t,
+                    Err(_) => {
+                        counts.skipped += 1;
+                        return;
+                    },
+                };
+
+                if existing.alias_of.is_some() {
+                    // replace the alias with the imported tag
+                    delete_tag_do(&ctx, existing.id);
+                    result = insert(&imported.key);
+                } else {
+                    if set_tag_embed(&ctx, existing.id, imported.embed.as_ref().map(String::as_str)).is_err() {
+                        counts.skipped += 1;
+                        return;
+                    }
+
+                    // keep the old content as a revision
+                    if existing.text != imported.content {
+                        update_tag_text(&ctx, &existing, &imported.content, msg.author.id.0 as i64);
+                    }
+
+                    counts.overwritten += 1;
+                    return;
+                }
+            },
+            ConflictPolicy::Rename => {
+                for n in 2..100 {
+                    let new_key = format!("{}-{}", imported.key, n);
+
+                    if new_key.len() >= 50 {
+                        break;
+                    }
+
+                    result = insert(&new_key);
+
+                    if !is_conflict(&result) {
+                        break;
+                    }
+                }
+            },
+        }
+    }
+
+    let t_id = match result {
+        Ok(t_id) => t_id,
+        Err(_) => {
+            counts.skipped += 1;
+            return;
+        },
+    };
+
+    // a tag that can't be completed is removed rather than left half imported
+    let t = match set_imported_fields(&ctx, t_id, imported).and_then(|_| get_tag_row_by_id(&ctx, t_id)) {
+        Ok(t) => t,
+        Err(_) => {
+            delete_tag_do(&ctx, t_id);
+            counts.skipped += 1;
+            return;
+        },
+    };
+
+    if t.key == imported.key {
+        counts.created += 1;
+    } else {
+        counts.renamed += 1;
+    }
+
+    // aliases that conflict with existing keys are left out
+    for alias in &imported.aliases {
+        if get_tag_row(&ctx, g_id, alias).is_ok()
+            || insert_alias(&ctx, imported.author_id as i64, alias, &t).is_err() {
+            counts.skipped_aliases.push(alias.clone());
+        }
+    }
+}
+
+
+fn get_tag_row_by_id(ctx: &Context, t_id: i64) -> QueryResult<Tag> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    tag.find(t_id).first(pool)
+}
+
+
+fn list_tags(ctx: &Context, s: TagScope, g_id: Option<i64>, u_id: i64, page: i64) -> PaginationResult<Tag> {
+    use schema::tag::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    match s {
+        TagScope::Guild => tag
+            .filter(guild_id.eq(g_id))
+            .filter(alias_of.is_null())
+            .order(key.asc())
+            .paginate(page)
+            .load_and_count_pages(pool)
+            .unwrap(),
+        TagScope::Personal => tag
+            .filter(scope.eq(s.as_str()))
+            .filter(author_id.eq(u_id))
+            .order(key.asc())
+            .paginate(page)
+            .load_and_count_pages(pool)
+            .unwrap(),
+        TagScope::Global => tag
+            .filter(scope.eq(s.as_str()))
+            .order(key.asc())
+            .paginate(page)
+            .load_and_count_pages(pool)
+            .unwrap(),
+    }
+}
+
+
+fn set_auto_tags(ctx: &Context, g_id: i64, value: bool) {
+    use schema::guild::dsl::*;
+
+    let pool = extract_pool!(&ctx);
+
+    diesel::update(guild.find(&g_id))
+        .set(tag_prefix_on.eq(value))
+        .execute(pool)
+        .unwrap();
+}
+
+
+/// Create a tag in a scope from a command, shared by the add tag commands
+fn add_tag_in(ctx: &Context, msg: &Message, mut args: Args, scope: TagScope) -> Result<(), CommandError> {
+    const MAX_ATTACHMENTS: usize = 3;
+    const MAX_ATTACHMENT_SIZE: u64 = 4 * 1024 * 1024;
+
+    let key = get_arg!(args, single_quoted, String, key);
+    let value = args.rest().trim();
+
+    let existing = match scope {
+        TagScope::Guild => get_tag_row(&ctx, msg.guild_id.unwrap().0 as i64, &key),
+        _ => get_unscoped_tag(&ctx, scope, msg.author.id.0 as i64, &key),
+    };
+
+    if scope == TagScope::Guild {
+        check_tag_limits(&ctx, &msg)?;
+    }
+
+    if let Ok(t) = existing {
+        void!(say(msg.channel_id, format!("The tag: {} already exists", t.key)));
+    } else if key.len() >= 50 {
+        void!(say(msg.channel_id, "Tag keys cannot be longer than 50 characters."));
+    } else if value.is_empty() && msg.attachments.is_empty() {
+        void!(say(msg.channel_id, "Tags need some content or an attachment."));
+    } else if msg.attachments.len() > MAX_ATTACHMENTS {
+        void!(say(msg.channel_id, format!("Tags can have at most {} attachments.", MAX_ATTACHMENTS)));
+    } else if msg.attachments.iter().any(|a| a.size > MAX_ATTACHMENT_SIZE) {
+        void!(say(msg.channel_id, "Tag attachments cannot be larger than 4MB."));
+    } else {
+        lazy_static! {
+            static ref UNSAFE_FILENAME_RE: Regex = Regex::new(r"[^A-Za-z0-9._-]").unwrap();
+        }
+
+        // saved so the tag keeps working after the message is deleted,
+        // filenames are cleaned so they can be referenced from embeds
+        let files = msg.attachments
+            .iter()
+            .map(|a| a.download().map(|data| (UNSAFE_FILENAME_RE.replace_all(&a.filename, "_").into_owned(), data)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let t_id = insert_tag(&ctx, &msg, scope, &key, &value);
+        insert_attachments(&ctx, t_id, &files);
+
+        void!(say(msg.channel_id, format!("Created {} tag: {} with content: {}!", scope.as_str(), key, value)));
+    }
+
+    Ok(())
+}
+
+
+/// Delete a personal or global tag from a command
+fn delete_unscoped_tag(ctx: &Context, msg: &Message, args: Args, scope: TagScope) -> Result<(), CommandError> {
+    let key = get_arg!(args, multiple, String, key).join(" ");
+
+    let t = get_unscoped_tag(&ctx, scope, msg.author.id.0 as i64, &key)
+        .map_err(|_| "That tag does not exist.")?;
+
+    delete_tag_do(&ctx, t.id);
+    void!(say(msg.channel_id, format!("Deleted {} tag of name: {}.", scope.as_str(), t.key)));
+
+    Ok(())
+}
+
+
+command!(add_tag_cmd(ctx, msg, args) {
+    add_tag_in(&ctx, &msg, args, TagScope::Guild)?;
+});
+
+
+command!(add_personal_tag_cmd(ctx, msg, args) {
+    add_tag_in(&ctx, &msg, args, TagScope::Personal)?;
+});
+
+
+command!(add_global_tag_cmd(ctx, msg, args) {
+    add_tag_in(&ctx, &msg, args, TagScope::Global)?;
+});
+
+
+command!(delete_personal_tag_cmd(ctx, msg, args) {
+    delete_unscoped_tag(&ctx, &msg, args, TagScope::Personal)?;
+});
+
+
+command!(delete_global_tag_cmd(ctx, msg, args) {
+    delete_unscoped_tag(&ctx, &msg, args, TagScope::Global)?;
+});
+
+
+command!(tag_cmd(ctx, msg, args) {
+    let words = get_arg!(args, multiple, String, key);
+
+    let g_id = msg.guild_id.map(|g| g.0 as i64);
+
+    // words after the tag key are arguments to the tag
+    if let Some((t, n)) = get_tag_with_args(&ctx, g_id, msg.author.id.0 as i64, &words) {
+        record_tag_use(&ctx, t.id);
+        send_tag(&ctx, &msg, &t, &words[n..]);
+    } else if let Some(g_id) = g_id {
+        void!(say(msg.channel_id, missing_tag_message(&ctx, g_id, &words.join(" "))));
+    } else {
+        void!(say(msg.channel_id, "That tag does not exist."));
+    }
+});
+
+
+command!(delete_tag(ctx, msg, args) {
+    let key = get_arg!(args, multiple, String, key).join(" ");
+
+    // deleting an alias only deletes the alias, deleting a tag deletes its aliases too
+    if let Ok(t) = get_tag_row(&ctx, msg.guild_id.unwrap().0 as i64, &key) {
+        if can_manage_tag(&msg, &t) {
+            delete_tag_do(&ctx, t.id);
+
+            if t.alias_of.is_some() {
+                void!(say(msg.channel_id, format!("Deleted tag alias of name: {}.", t.key)));
+            } else {
+                void!(say(msg.channel_id, format!("Deleted tag of name: {}.", t.key)));
+            }
+        } else {
+            void!(say(msg.channel_id, "You are not the owner of this tag or do not have manage messages."));
+        }
+    } else {
+        void!(say(msg.channel_id, missing_tag_message(&ctx, msg.guild_id.unwrap().0 as i64, &key)));
+    }
+});
+
+
+command!(tag_alias_cmd(ctx, msg, args) {
+    let g_id = msg.guild_id.unwrap().0 as i64;
+
+    let existing = get_arg!(args, single_quoted, String, existing);
+    let new_key = get_arg!(args, single_quoted, String, new);
+
+    let t = get_tag_or_suggest(&ctx, g_id, &existing)?;
+
+    if let Ok(t) = get_tag_row(&ctx, g_id, &new_key) {
+        return Err(format!("The tag: {} already exists", t.key).into());
+    }
+
+    if new_key.len() >= 50 {
+        return Err("Tag keys cannot be longer than 50 characters.".into());
+    }
+
+    insert_alias(&ctx, msg.author.id.0 as i64, &new_key, &t).expect("Couldn't insert tag alias");
+
+    void!(say(msg.channel_id, format!("Created alias: {} for the tag: {}!", new_key, t.key)));
+});
+
+
+command!(edit_tag_cmd(ctx, msg, args) {
+    let key = get_arg!(args, single_quoted, String, key);
+    let value = args.rest().trim();
+
+    let t = get_tag_or_suggest(&ctx, msg.guild_id.unwrap().0 as i64, &key)?;
+
+    if !can_manage_tag(&msg, &t) {
+        return Err("You are not the owner of this tag or do not have manage messages.".into());
+    }
+
+    if value.is_empty() {
+        return Err("Tags cannot be empty.".into());
+    }
+
+    if value == t.text {
+        return Err("The tag already has that content.".into());
+    }
+
+    update_tag_text(&ctx, &t, value, msg.author.id.0 as i64);
+
+    void!(say(msg.channel_id, format!("Edited tag: {}, the old content can be found with tag_history.", t.key)));
+});
+
+
+command!(tag_embed_cmd(ctx, msg, args) {
+    let key = get_arg!(args, single_quoted, String, key);
+
+    // allow the JSON to be wrapped in a code block
+    let json = args.rest().trim().trim_matches('`');
+    let json = if json.starts_with("json") { &json[4..] } else { json };
+
+    let t = get_tag_or_suggest(&ctx, msg.guild_id.unwrap().0 as i64, &key)?;
+
+    if !can_manage_tag(&msg, &t) {
+        return Err("You are not the owner of this tag or do not have manage messages.".into());
+    }
+
+    if json.trim().is_empty() {
+        set_tag_embed(&ctx, t.id, None)?;
+        void!(say(msg.channel_id, format!("Removed the embed from tag: {}.", t.key)));
+    } else {
+        parse_tag_embed(json)?;
+        set_tag_embed(&ctx, t.id, Some(json.trim()))?;
+        void!(say(msg.channel_id, format!("Set the embed of tag: {}.", t.key)));
+    }
+});
+
 
-    tag.filter(guild_id.eq(&g_id))
-       .filter(key.eq(tag_key))
-       .first(pool)
-}
+command!(tag_transfer_cmd(ctx, msg, args) {
+    let key = get_arg!(args, single_quoted, String, key);
+    let new_author = get_arg!(args, single, UserId, user);
 
+    let g_id = msg.guild_id.unwrap();
 
-fn insert_tag(ctx: &Context, msg: &Message, key: &str, content: &str) {
-    use schema::tag;
-    use models::NewTag;
+    let t = get_tag_or_suggest(&ctx, g_id.0 as i64, &key)?;
 
-    let new_tag =  NewTag {
-        author_id: msg.author.id.0 as i64,
-        guild_id: msg.guild_id.unwrap().0 as i64,
-        key,
-        text: content,
-    };
+    if !can_manage_tag(&msg, &t) {
+        return Err("You are not the owner of this tag or do not have manage messages.".into());
+    }
 
-    let pool = extract_pool!(&ctx);
+    if !is_guild_member(g_id, new_author)? {
+        return Err("Tags can only be given to members of this guild.".into());
+    }
 
-    diesel::insert_into(tag::table)
-        .values(&new_tag)
-        .execute(pool)
-        .expect("Couldn't insert tag");
-}
+    set_tag_author(&ctx, t.id, new_author.0 as i64);
 
+    let content = MessageBuilder::new()
+        .push("Gave the tag: ")
+        .push_safe(&t.key)
+        .push(" to ")
+        .mention(&new_author);
 
-fn delete_tag_do(ctx: &Context, tag_id: i64) {
-    use schema::tag::dsl::*;
+    void!(say(msg.channel_id, content));
+});
 
-    let pool = extract_pool!(&ctx);
 
-    diesel::delete(tag.filter(id.eq(tag_id)))
-        .execute(pool)
-        .unwrap();
-}
+command!(tag_claim_cmd(ctx, msg, args) {
+    let key = get_arg!(args, multiple, String, key).join(" ");
 
+    let g_id = msg.guild_id.unwrap();
 
-fn list_tags(ctx: &Context, g_id: i64, page: i64) -> PaginationResult<Tag> {
-    use schema::tag::dsl::*;
+    let t = get_tag_or_suggest(&ctx, g_id.0 as i64, &key)?;
 
-    let pool = extract_pool!(&ctx);
+    if t.author_id as u64 == msg.author.id.0 {
+        return Err("You already own this tag.".into());
+    }
 
-    tag.filter(guild_id.eq(&g_id))
-       .order(key.asc())
-       .paginate(page)
-       .load_and_count_pages(pool)
-       .unwrap()
-}
+    if is_guild_member(g_id, UserId::from(t.author_id as u64))? {
+        return Err("The owner of this tag is still in this guild, ask them to transfer it with tag_transfer.".into());
+    }
 
+    set_tag_author(&ctx, t.id, msg.author.id.0 as i64);
 
-fn set_auto_tags(ctx: &Context, g_id: i64, value: bool) {
-    use schema::guild::dsl::*;
+    void!(say(msg.channel_id, format!("You now own the tag: {}.", t.key)));
+});
 
-    let pool = extract_pool!(&ctx);
 
-    diesel::update(guild.find(&g_id))
-        .set(tag_prefix_on.eq(value))
-        .execute(pool)
-        .unwrap();
-}
+command!(tag_history_cmd(ctx, msg, args) {
+    use utils::names_for_members;
 
+    let key = get_arg!(args, single_quoted, String, key);
+    let page = args.single::<i64>().unwrap_or(1);
 
-command!(add_tag_cmd(ctx, msg, args) {
+    if page <= 0 {
+        return Err("That page does not exist.".into());
+    }
+
+    let t = get_tag_or_suggest(&ctx, msg.guild_id.unwrap().0 as i64, &key)?;
+
+    let revisions = list_revisions(&ctx, t.id, page);
+
+    if !revisions.page_exists() {
+        return Err("That page does not exist or this tag has never been edited.".into());
+    }
+
+    let user_ids: Vec<u64> = revisions.results.iter().map(|&(_, u, _)| u as u64).collect();
+    let user_names = names_for_members(&user_ids, msg.guild_id.unwrap());
+
+    let history = user_names
+        .into_iter()
+        .zip(revisions.iter_with_indexes())
+        .map(|(name, (&(ref when, _, ref text), i))| {
+            let preview: String = text.chars().take(40).collect();
+            format!("{:>3} | {} | replaced by {}: {}", i, when.format("%Y-%m-%d %H:%M"), name, preview)
+        })
+        .join("\n");
+
+    let content = MessageBuilder::new()
+        .push_line(format!("Previous versions of {}, restore one with tag_revert:", t.key))
+        .push_codeblock_safe(history, None)
+        .push_line(format!("Page {} of {}", revisions.page, revisions.total_pages));
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(tag_revert_cmd(ctx, msg, args) {
     let key = get_arg!(args, single_quoted, String, key);
-    let value = args.rest().trim();
+    let rev = get_arg!(args, single, usize, revision) as i64;
 
-    if let Ok(t) = get_tag(&ctx, msg.guild_id.unwrap().0 as i64, &key) {
-        void!(say(msg.channel_id, format!("The tag: {} already exists", t.key)));
-    } else if key.len() >= 50 {
-        void!(say(msg.channel_id, "Tag keys cannot be longer than 50 characters."));
-    } else {
-        insert_tag(&ctx, &msg, &key, &value);
-        void!(say(msg.channel_id, format!("Created tag: {} with content: {}!", key, value)));
+    let t = get_tag_or_suggest(&ctx, msg.guild_id.unwrap().0 as i64, &key)?;
+
+    if !can_manage_tag(&msg, &t) {
+        return Err("You are not the owner of this tag or do not have manage messages.".into());
     }
+
+    let revision = get_revision(&ctx, t.id, rev).ok_or("That revision does not exist.")?;
+
+    // the current content is kept as a revision too, so reverts can be undone
+    update_tag_text(&ctx, &t, &revision.text, msg.author.id.0 as i64);
+
+    void!(say(msg.channel_id, format!("Reverted tag: {} to revision {}.", t.key, rev)));
 });
 
 
-command!(tag_cmd(ctx, msg, args) {
-    let key = get_arg!(args, multiple, String, key).join(" ");
+command!(tag_search_cmd(ctx, msg, args) {
+    let query = args.full().trim();
 
-    if let Ok(t) = get_tag(&ctx, msg.guild_id.unwrap().0 as i64, &key) {
-        void!(say(msg.channel_id, t.text));
-    } else {
-        void!(say(msg.channel_id, "This tag does not exist."));
+    if query.is_empty() {
+        return Err("Give something to search for.".into());
+    }
+
+    let matches = search_tags(&ctx, msg.guild_id.unwrap().0 as i64, query);
+
+    if matches.is_empty() {
+        return Err("No tags matched your search.".into());
     }
+
+    let results = matches
+        .iter()
+        .map(|m| {
+            let preview: String = m.text.chars().take(50).collect();
+            format!("{}: {}", m.key, preview)
+        })
+        .join("\n");
+
+    let content = MessageBuilder::new()
+        .push_line("Matching tags:")
+        .push_codeblock_safe(results, None);
+
+    void!(say(msg.channel_id, content));
 });
 
 
-command!(delete_tag(ctx, msg, args) {
+command!(tag_info_cmd(ctx, msg, args) {
+    use utils::names_for_members;
+
     let key = get_arg!(args, multiple, String, key).join(" ");
 
-    if let Ok(t) = get_tag(&ctx, msg.guild_id.unwrap().0 as i64, &key) {
+    let t = get_tag_or_suggest(&ctx, msg.guild_id.unwrap().0 as i64, &key)?;
 
-        let has_manage_messages = log_time!(with_cache(
-            |cache| cache.guild(msg.guild_id.unwrap()).map_or(
-                false,
-                |g| g.read().member_permissions(msg.author.id).manage_messages()
-            )
-        ), "with_cache: has_manage_messages");
+    let author = names_for_members(&[t.author_id as u64], msg.guild_id.unwrap())
+        .pop()
+        .unwrap_or_default();
 
-        if has_manage_messages || (t.author_id as u64 == msg.author.id.0) {
-            delete_tag_do(&ctx, t.id);
-            void!(say(msg.channel_id, format!("Deleted tag of name: {}.", t.key)));
-        } else {
-            void!(say(msg.channel_id, "You are not the owner of this tag or do not have manage messages."));
-        }
+    let format_time = |time: Option<NaiveDateTime>| time
+        .map_or_else(|| "unknown".to_owned(), |w| format!("{} UTC", w.format("%Y-%m-%d %H:%M")));
+
+    let aliases = aliases_of(&ctx, &[t.id]).pop().unwrap_or_default();
+
+    let content = MessageBuilder::new()
+        .push("Tag: ").push_bold_safe(&t.key)
+        .push("\nAuthor: ").push_safe(author)
+        .push("\nCreated: ").push(format_time(t.created_at))
+        .push("\nUses: ").push(t.uses)
+        .push("\nLast used: ").push(if t.last_used.is_some() { format_time(t.last_used) } else { "never".to_owned() });
+
+    let content = if aliases.is_empty() {
+        content
     } else {
-        void!(say(msg.channel_id, "That tag does not exist."));
+        content.push("\nAliases: ").push_safe(aliases.join(", "))
+    };
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(top_tags_cmd(ctx, msg, args) {
+    let page = args.single::<i64>().unwrap_or(1);
+
+    if page <= 0 {
+        return Err("That page does not exist.".into());
+    }
+
+    let tags = top_tags(&ctx, msg.guild_id.unwrap().0 as i64, page);
+
+    if !tags.page_exists() {
+        return Err("That page does not exist or no tags exist for this server.".into());
     }
+
+    let block = tags.block(|(ref k, ref u, ref l), i| {
+        let last_used = l.map_or_else(|| "never".to_owned(), |w| w.format("%Y-%m-%d").to_string());
+        format!("{:>3} | {:>5} uses | last used {:<10} | {}", i, u, last_used, k)
+    });
+
+    let content = MessageBuilder::new()
+        .push_line("Most used tags:")
+        .push(block);
+
+    void!(say(msg.channel_id, content));
 });
 
 
 command!(list_tags_cmd(ctx, msg, args) {
     use utils::names_for_members;
 
-    let page = args.single::<i64>().unwrap_or(1);
+    let default_scope = if msg.guild_id.is_some() { TagScope::Guild } else { TagScope::Personal };
+
+    // the scope is optional, so the first argument might be the page
+    let first = args.single::<String>().ok();
+
+    let (scope, page) = match first.as_ref().and_then(|s| TagScope::from_name(&s.to_lowercase())) {
+        Some(s) => (s, args.single::<i64>().unwrap_or(1)),
+        None => (default_scope, first.and_then(|p| p.parse().ok()).unwrap_or(1)),
+    };
 
     if page <= 0 {
         return Err("That page does not exist.".into());
     }
 
-    let tags = list_tags(&ctx, msg.guild_id.unwrap().0 as i64, page);
+    if scope == TagScope::Guild && msg.guild_id.is_none() {
+        return Err("Guild tags can only be listed in a guild.".into());
+    }
+
+    let tags = list_tags(&ctx, scope, msg.guild_id.map(|g| g.0 as i64), msg.author.id.0 as i64, page);
 
     if !tags.page_exists() {
-        return Err("That page does not exist or no tags exist for this server.".into());
+        return Err(format!("That page does not exist or no {} tags exist.", scope.as_str()).into());
     }
 
-    let user_ids: Vec<u64> = tags.results.iter().map(|t| t.author_id as u64).collect();
+    let tag_content = if scope == TagScope::Guild {
+        let user_ids: Vec<u64> = tags.results.iter().map(|t| t.author_id as u64).collect();
 
-    let user_names = names_for_members(&user_ids, msg.guild_id.unwrap());
+        let user_names = names_for_members(&user_ids, msg.guild_id.unwrap());
 
-    let tag_content = user_names
-        .into_iter()
-        .zip(tags.iter_with_indexes())
-        .map(|(name, (tag_v, i))| format!("{:>3} | {}: {}", i, name, tag_v.key))
-        .join("\n");
+        let tag_ids: Vec<i64> = tags.results.iter().map(|t| t.id).collect();
+        let aliases = aliases_of(&ctx, &tag_ids);
+
+        user_names
+            .into_iter()
+            .zip(aliases)
+            .zip(tags.iter_with_indexes())
+            .map(|((name, aliases), (tag_v, i))| if aliases.is_empty() {
+                format!("{:>3} | {}: {}", i, name, tag_v.key)
+            } else {
+                format!("{:>3} | {}: {} (also: {})", i, name, tag_v.key, aliases.join(", "))
+            })
+            .join("\n")
+    } else {
+        tags.lines(|t, i| format!("{:>3} | {}", i, t.key))
+    };
 
     let content = MessageBuilder::new()
         .push_codeblock_safe(tag_content, None)
@@ -177,6 +1668,120 @@ command!(list_tags_cmd(ctx, msg, args) {
 });
 
 
+command!(tags_export_cmd(ctx, msg) {
+    let tags = export_tags(&ctx, msg.guild_id.unwrap().0 as i64);
+
+    if tags.is_empty() {
+        return Err("This guild has no tags to export.".into());
+    }
+
+    let export = write_tags_export(&tags);
+
+    msg.channel_id.send_files(vec![(export.as_bytes(), "tags.json")],
+                              |m| m.content(format!("Exported {} tags, attachments are not included.", tags.len())))?;
+});
+
+
+command!(tags_import_cmd(ctx, msg, args) {
+    // limits so a huge export can't flood the tag table
+    const MAX_SIZE: u64 = 2_000_000;
+    const MAX_TAGS: usize = 2000;
+
+    let policy = match get_arg!(args, single, String, policy, "skip".to_owned()).to_lowercase().as_str() {
+        "skip" => ConflictPolicy::Skip,
+        "overwrite" => ConflictPolicy::Overwrite,
+        "rename" => ConflictPolicy::Rename,
+        _ => return Err("The conflict policy must be one of: skip, overwrite or rename.".into()),
+    };
+
+    let attachment = msg.attachments
+        .iter()
+        .find(|a| a.filename.to_lowercase().ends_with(".json"))
+        .ok_or("Attach a .json file made by tags_export to import.")?;
+
+    if attachment.size > MAX_SIZE {
+        return Err("That export is too large.".into());
+    }
+
+    let content = String::from_utf8(attachment.download()?)?;
+    let tags = parse_tags_export(&content)?;
+
+    if tags.len() > MAX_TAGS {
+        return Err(format!("Exports can have at most {} tags.", MAX_TAGS).into());
+    }
+
+    let g_id = msg.guild_id.unwrap().0 as i64;
+    let mut counts = ImportCounts::default();
+
+    for imported in &tags {
+        import_tag(&ctx, &msg, g_id, imported, policy, &mut counts);
+    }
+
+    let mut content = MessageBuilder::new()
+        .push(format!("Imported {} tags, skipped {}, overwrote {} and renamed {}.",
+                      counts.created, counts.skipped, counts.overwritten, counts.renamed));
+
+    if !counts.skipped_aliases.is_empty() {
+        content = content
+            .push_line("")
+            .push_line(format!("Skipped {} aliases with the same key as an existing tag:", counts.skipped_aliases.len()))
+            .push_codeblock_safe(counts.skipped_aliases.iter().take(20).join(", "), None);
+    }
+
+    void!(say(msg.channel_id, content));
+});
+
+
+command!(tag_limits_cmd(ctx, msg, args) {
+    lazy_static! {
+        static ref ROLE_RE: Regex = Regex::new(r"^(?:<@&)?(?P<id>\d+)>?$").unwrap();
+    }
+
+    let g_id = msg.guild_id.unwrap();
+    let mut limits = get_tag_limits(&ctx, g_id.0 as i64);
+
+    if args.is_empty() {
+        let describe = |l: Option<i32>| l.map_or_else(|| "none".to_owned(), |l| l.to_string());
+
+        let content = MessageBuilder::new()
+            .push_line("Tag limits for this server:")
+            .push_line(format!("Tags per member: {}", describe(limits.per_user)))
+            .push_line(format!("Tags in the server: {}", describe(limits.per_guild)))
+            .push(format!("Role needed to create tags: {}", limits.create_role.map_or_else(|| "none".to_owned(), role_label)));
+
+        void!(say(msg.channel_id, content));
+        return Ok(());
+    }
+
+    let setting = get_arg!(args, single, String, setting).to_lowercase();
+    let value = args.rest().trim();
+
+    match setting.as_str() {
+        "user" | "member" => limits.per_user = parse_tag_limit(value)?,
+        "guild" | "server" => limits.per_guild = parse_tag_limit(value)?,
+        "role" => {
+            limits.create_role = if value.eq_ignore_ascii_case("none") || value.eq_ignore_ascii_case("off") {
+                None
+            } else {
+                let found = match ROLE_RE.captures(value) {
+                    Some(caps) => caps["id"].parse::<u64>().ok()
+                        .filter(|r| g_id.to_guild_cached().map_or(false, |g| g.read().roles.contains_key(&RoleId(*r)))),
+                    None => g_id.to_guild_cached()
+                        .and_then(|g| g.read().role_by_name(value).map(|r| r.id.0)),
+                };
+
+                Some(found.ok_or("That role is not in this server.")? as i64)
+            };
+        },
+        _ => return Err("The setting must be one of: user, guild or role.".into()),
+    }
+
+    set_tag_limits(&ctx, g_id.0 as i64, &limits);
+
+    void!(say(msg.channel_id, "Updated the tag limits of this server."));
+});
+
+
 command!(auto_tags_on(ctx, msg) {
     set_auto_tags(&ctx, msg.guild_id.unwrap().0 as i64, true);
     void!(say(msg.channel_id, "Enabled automatic tags on this guild."));
@@ -189,40 +1794,193 @@ command!(auto_tags_off(ctx, msg) {
 });
 
 
-pub fn setup_tags(_client: &mut Client, frame: StandardFramework) -> StandardFramework {
+pub fn setup_tags(client: &mut Client, frame: StandardFramework) -> StandardFramework {
     frame.group("Tags",
-                |g| g
-                .guild_only(true)
+                register(client, |g| g
                 .command(
                     "add_tag", |c| c
+                        .guild_only(true)
                         .cmd(add_tag_cmd)
-                        .desc("Create a tag with a name and response.")
-                        .example("\"something\" This tag's content.")
+                        .desc(concat!(
+                            "Create a tag with a name and response, attachments on the message are saved with the tag. ",
+                            "Responses can use ",
+                            "{user}, {user.mention}, {channel}, {guild}, {args}, {arg:1}, ",
+                            "{choose:a|b|c} and {rand:1-6}, which are filled in when the tag is used."))
+                        .example("\"roll\" {user} rolled a {rand:1-6}")
                         .usage("{tag name} {tag content}")
                 )
                 .command(
                     "tag", |c| c
                         .cmd(tag_cmd)
-                        .desc("Retrieve a tag.")
+                        .desc(concat!(
+                            "Retrieve a tag, any words after the tag name are given to the tag as arguments. ",
+                            "Tags from this guild are used first, then your personal tags, then global tags."))
                         .example("\"something\"")
+                        .usage("{tag name} {args...}")
+                )
+                .command(
+                    "add_personal_tag", |c| c
+                        .cmd(add_personal_tag_cmd)
+                        .desc("Create a personal tag, which you can use in any guild or in DMs.")
+                        .example("\"something\" This tag's content.")
+                        .usage("{tag name} {tag content}")
+                        .batch_known_as(&["add_ptag"])
+                )
+                .command(
+                    "add_global_tag", |c| c
+                        .cmd(add_global_tag_cmd)
+                        .desc("Create a global tag, which anyone can use anywhere.")
+                        .example("\"something\" This tag's content.")
+                        .usage("{tag name} {tag content}")
+                        .owners_only(true)
+                )
+                .command(
+                    "delete_personal_tag", |c| c
+                        .cmd(delete_personal_tag_cmd)
+                        .desc("Delete one of your personal tags.")
+                        .example("tag name")
+                        .usage("{tag name}")
+                        .batch_known_as(&["delete_ptag"])
+                )
+                .command(
+                    "delete_global_tag", |c| c
+                        .cmd(delete_global_tag_cmd)
+                        .desc("Delete a global tag.")
+                        .example("tag name")
                         .usage("{tag name}")
+                        .owners_only(true)
                 )
                 .command(
                     "delete_tag", |c| c
+                        .guild_only(true)
                         .cmd(delete_tag)
                         .desc("Delete a tag, only the owner of the tag, or a member with manage message perms can delete tags.")
                         .example("tag name")
                         .usage("{tag name}")
                 )
+                .command(
+                    "tag_alias", |c| c
+                        .guild_only(true)
+                        .cmd(tag_alias_cmd)
+                        .desc("Add another name for an existing tag, deleting the tag also deletes its aliases.")
+                        .example("\"something\" \"something else\"")
+                        .usage("{existing tag name} {new tag name}")
+                )
+                .command(
+                    "edit_tag", |c| c
+                        .guild_only(true)
+                        .cmd(edit_tag_cmd)
+                        .desc("Change the content of a tag, only the owner of the tag, or a member with manage message perms can edit tags.")
+                        .example("\"something\" The new content.")
+                        .usage("{tag name} {tag content}")
+                )
+                .command(
+                    "tag_embed", |c| c
+                        .guild_only(true)
+                        .cmd(tag_embed_cmd)
+                        .desc(concat!(
+                            "Set an embed to send with a tag, given as JSON with a title, description, colour ",
+                            "and fields. An image attached to the tag is shown in the embed. ",
+                            "Leave out the JSON to remove the embed."))
+                        .example(r##""something" {"title": "Hi {user}", "colour": "#ff0000", "fields": [{"name": "a", "value": "b", "inline": true}]}"##)
+                        .usage("{tag name} {embed json}")
+                )
+                .command(
+                    "tag_transfer", |c| c
+                        .guild_only(true)
+                        .cmd(tag_transfer_cmd)
+                        .desc("Give a tag to another member, only the owner of the tag, or a member with manage message perms can do this.")
+                        .example("\"something\" @someone")
+                        .usage("{tag name} {user}")
+                )
+                .command(
+                    "tag_claim", |c| c
+                        .guild_only(true)
+                        .cmd(tag_claim_cmd)
+                        .desc("Take ownership of a tag whose owner has left this guild.")
+                        .example("tag name")
+                        .usage("{tag name}")
+                )
+                .command(
+                    "tag_history", |c| c
+                        .guild_only(true)
+                        .cmd(tag_history_cmd)
+                        .desc("List the previous versions of a tag.")
+                        .example("\"something\" 1")
+                        .usage("{tag name} {page}")
+                )
+                .command(
+                    "tag_revert", |c| c
+                        .guild_only(true)
+                        .cmd(tag_revert_cmd)
+                        .desc("Restore a previous version of a tag, the revision numbers are shown by tag_history.")
+                        .example("\"something\" 2")
+                        .usage("{tag name} {revision}")
+                )
+                .command(
+                    "tag_search", |c| c
+                        .guild_only(true)
+                        .cmd(tag_search_cmd)
+                        .desc("Search the names and content of tags in this guild.")
+                        .example("cat pictures")
+                        .usage("{query}")
+                        .batch_known_as(&["search_tags"])
+                )
                 .command(
                     "list_tags", |c| c
                         .cmd(list_tags_cmd)
-                        .desc("List tags for this guild.")
-                        .example("1 -- lists tags on the first page")
+                        .desc("List tags for this guild, your personal tags or global tags.")
+                        .example("personal 1 -- lists your personal tags on the first page")
+                        .usage("{guild|personal|global} {page}")
+                )
+                .command(
+                    "tag_info", |c| c
+                        .guild_only(true)
+                        .cmd(tag_info_cmd)
+                        .desc("Show who made a tag, when, and how often it has been used.")
+                        .example("tag name")
+                        .usage("{tag name}")
+                )
+                .command(
+                    "top_tags", |c| c
+                        .guild_only(true)
+                        .cmd(top_tags_cmd)
+                        .desc("List the tags of this guild by how often they are used.")
+                        .example("1 -- lists the most used tags")
                         .usage("{page}")
                 )
+                .command(
+                    "tag_limits", |c| c
+                        .guild_only(true)
+                        .cmd(tag_limits_cmd)
+                        .desc(concat!(
+                            "View or change the limits on creating tags in this server: ",
+                            "the tags each member can make, the tags in the server and a role needed to create tags. ",
+                            "Use `none` to remove a limit, members with manage messages only have the server limit."))
+                        .example("user 20")
+                        .usage("[{user|guild} {limit|none}] [role {role|none}]")
+                        .required_permissions(Permissions::ADMINISTRATOR)
+                )
+                .command(
+                    "tags_export", |c| c
+                        .guild_only(true)
+                        .cmd(tags_export_cmd)
+                        .desc("Export the tags of this guild as a JSON file, attachments are not included.")
+                )
+                .command(
+                    "tags_import", |c| c
+                        .guild_only(true)
+                        .cmd(tags_import_cmd)
+                        .desc(concat!(
+                            "Import tags from an attached file made by tags_export. ",
+                            "Tags with names that already exist are skipped, overwritten, or renamed, skip is the default."))
+                        .example("rename")
+                        .usage("{skip|overwrite|rename}")
+                        .required_permissions(Permissions::ADMINISTRATOR)
+                )
                 .command(
                     "auto_tags_on", |c| c
+                        .guild_only(true)
                         .cmd(auto_tags_on)
                         .desc(concat!(
                             "By enabling this, you can allow tags to be ",
@@ -232,9 +1990,111 @@ pub fn setup_tags(_client: &mut Client, frame: StandardFramework) -> StandardFra
                 )
                 .command(
                     "auto_tags_off", |c| c
+                        .guild_only(true)
                         .cmd(auto_tags_off)
                         .desc("Disables the prefix only tagging that is enabled by the command: 'auto_tags_on'")
                         .required_permissions(Permissions::ADMINISTRATOR)
                 )
-    )
+    ))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_tag_limit() {
+        assert_eq!(parse_tag_limit("20"), Ok(Some(20)));
+        assert_eq!(parse_tag_limit("None"), Ok(None));
+        assert!(parse_tag_limit("-1").is_err());
+        assert!(parse_tag_limit("lots").is_err());
+    }
+
+    #[test]
+    fn test_tags_export_round_trip() {
+        let time = NaiveDateTime::parse_from_str("2019-03-06 12:00:00", "%Y-%m-%d %H:%M:%S").unwrap();
+
+        let tags = vec![
+            ExportedTag {
+                key: "a".to_owned(),
+                content: "hello {user}".to_owned(),
+                author_id: 1234,
+                created_at: Some(time),
+                last_used: None,
+                uses: 3,
+                embed: Some(r#"{"title":"hi"}"#.to_owned()),
+                aliases: vec!["b".to_owned(), "c".to_owned()],
+            },
+            ExportedTag {
+                key: "d".to_owned(),
+                content: "".to_owned(),
+                author_id: 5678,
+                created_at: None,
+                last_used: Some(time),
+                uses: 0,
+                embed: None,
+                aliases: vec![],
+            },
+        ];
+
+        assert_eq!(parse_tags_export(&write_tags_export(&tags)), Ok(tags));
+
+        assert!(parse_tags_export("[]").is_err());
+        assert!(parse_tags_export(r#"{"tags": [{"content": "no key"}]}"#).is_err());
+        assert!(parse_tags_export(r#"{"tags": [{"key": "a", "embed": "not json"}]}"#).is_err());
+
+        // embeds are stored compactly, and rejected if even that is too long for the column
+        let padded = json!({"tags": [{"key": "a", "embed": format!("{{{}\"title\": \"hi\"}}", " ".repeat(10000))}]});
+        assert_eq!(parse_tags_export(&padded.to_string()).unwrap()[0].embed, Some(r#"{"title":"hi"}"#.to_owned()));
+
+        let quotes = json!({"title": "\"".repeat(256), "description": "\"".repeat(2048), "fields": vec![json!({"name": "\"", "value": "\"".repeat(1024)}); 3]});
+        let escaped = json!({"tags": [{"key": "a", "embed": quotes.to_string()}]});
+        assert!(parse_tags_export(&escaped.to_string()).is_err());
+
+        let clamped = parse_tags_export(r#"{"tags": [{"key": "a", "uses": 99999999999}, {"key": "b", "uses": -5}]}"#).unwrap();
+        assert_eq!(clamped[0].uses, std::i32::MAX);
+        assert_eq!(clamped[1].uses, 0);
+    }
+
+    #[test]
+    fn test_parse_tag_embed() {
+        let embed = parse_tag_embed(r##"{"title": "a", "colour": "#ff0000", "fields": [{"name": "b", "value": "c", "inline": true}]}"##).unwrap();
+        assert_eq!(embed.title, Some("a".to_owned()));
+        assert_eq!(embed.description, None);
+        assert_eq!(embed.colour, Some(0xff0000));
+        assert_eq!(embed.fields, vec![("b".to_owned(), "c".to_owned(), true)]);
+
+        assert_eq!(parse_tag_embed(r#"{"description": "a", "color": 255}"#).unwrap().colour, Some(255));
+
+        assert!(parse_tag_embed("not json").is_err());
+        assert!(parse_tag_embed("[]").is_err());
+        assert!(parse_tag_embed("{}").is_err());
+        assert!(parse_tag_embed(r#"{"title": 1}"#).is_err());
+        assert!(parse_tag_embed(r##"{"title": "a", "colour": "#1000000"}"##).is_err());
+        assert!(parse_tag_embed(r#"{"fields": [{"name": "a"}]}"#).is_err());
+
+        let field = json!({"name": "n".repeat(256), "value": "v".repeat(1024)});
+        assert!(parse_tag_embed(&json!({"fields": vec![field; 5]}).to_string()).is_err());
+    }
+
+    #[test]
+    fn test_render_tag_embed() {
+        let embed = TagEmbed {
+            title: Some("{args}".to_owned()),
+            description: Some("{args}".to_owned()),
+            colour: None,
+            fields: vec![("{args}".to_owned(), "{args}".to_owned(), false); 25],
+        };
+
+        let rendered = embed.render(|s| s.replace("{args}", &"x".repeat(3000)));
+
+        assert_eq!(rendered.title.as_ref().map(|t| t.len()), Some(EMBED_TITLE_LIMIT));
+        assert_eq!(rendered.description.as_ref().map(|d| d.len()), Some(EMBED_DESCRIPTION_LIMIT));
+        assert!(rendered.fields.iter().all(|&(ref n, ref v, _)| n.len() <= EMBED_FIELD_NAME_LIMIT && v.len() <= EMBED_FIELD_VALUE_LIMIT));
+        assert!(rendered.len() <= EMBED_TOTAL_LIMIT);
+
+        let rendered = embed.render(|s| s.replace("{args}", ""));
+        assert_eq!(rendered.title, Some("\u{200b}".to_owned()));
+    }
 }
@@ -20,6 +20,7 @@ extern crate serde_json;
 extern crate failure;
 extern crate base64;
 extern crate chrono;
+extern crate chrono_tz;
 extern crate dotenv;
 extern crate fern;
 extern crate itertools;
@@ -39,14 +40,19 @@ extern crate whirlpool;
 use serenity::{
     client::bridge::gateway::ShardManager,
     framework::{standard::StandardFramework, Framework},
-    model::{channel::Message, gateway::Ready, guild::Guild, id::GuildId},
+    model::{
+        channel::{Message, Reaction},
+        gateway::Ready,
+        guild::Guild,
+        id::{GuildId, UserId},
+    },
     prelude::*,
 };
 
 use diesel::{pg::PgConnection, prelude::*, r2d2::ConnectionManager};
 
 use lru_cache::LruCache;
-use std::{collections::HashSet, os::unix::net::UnixStream, sync::Arc};
+use std::{collections::HashSet, os::unix::net::UnixStream, sync::Arc, time::Instant};
 use threadpool::ThreadPool;
 use typemap::Key;
 use utils::{say, with_pool};
@@ -72,6 +78,10 @@ impl EventHandler for Handler {
         use models::NewStoredMessage;
         use schema::message;
 
+        if !msg.author.bot {
+            commands::responders::process_responders(&ctx, &msg);
+        }
+
         if !commands::markov::message_filter(&msg) {
             return;
         }
@@ -146,6 +156,14 @@ impl EventHandler for Handler {
         }
     }
 
+    fn reaction_add(&self, ctx: Context, reaction: Reaction) {
+        commands::events::process_rsvp(&ctx, &reaction, true);
+    }
+
+    fn reaction_remove(&self, ctx: Context, reaction: Reaction) {
+        commands::events::process_rsvp(&ctx, &reaction, false);
+    }
+
     fn resume(&self, _ctx: Context, evt: serenity::model::event::ResumedEvent) {
         debug!(target: "bot", "Got resume: {:?}", evt);
     }
@@ -240,6 +258,30 @@ impl Key for PrefixCache {
     type Value = LruCache<GuildId, Arc<RwLock<Vec<String>>>>;
 }
 
+struct ResponderCache;
+
+impl Key for ResponderCache {
+    type Value = LruCache<GuildId, Arc<commands::responders::GuildResponders>>;
+}
+
+struct ResponderCooldowns;
+
+impl Key for ResponderCooldowns {
+    type Value = LruCache<i64, Instant>;
+}
+
+struct PendingAliasImports;
+
+impl Key for PendingAliasImports {
+    type Value = LruCache<UserId, commands::aliases::PendingAliasImport>;
+}
+
+struct CommandRegistry;
+
+impl Key for CommandRegistry {
+    type Value = commands::Commands;
+}
+
 struct ThreadPoolCache;
 
 impl Key for ThreadPoolCache {
@@ -298,8 +340,8 @@ fn get_prefixes(ctx: &mut Context, m: &Message) -> Option<Arc<RwLock<Vec<String>
 
 /// Process possible tag activations
 fn process_tag(ctx: &mut Context, msg: &Message, cmd_name: &str) {
+    use commands::tags::find_tag;
     use schema::guild::dsl::*;
-    use schema::tag::dsl::*;
 
     let g_id = match msg.guild_id {
         Some(x) => x.0 as i64,
@@ -315,38 +357,68 @@ fn process_tag(ctx: &mut Context, msg: &Message, cmd_name: &str) {
     });
 
     if has_auto_tags {
-        if let Ok(r_tag) = with_pool(&ctx, |pool| {
-            tag.filter(guild_id.eq(g_id))
-                .filter(key.eq(cmd_name))
-                .select(text)
-                .first::<String>(&pool)
-        }) {
-            void!(say(msg.channel_id, r_tag));
+        if let Some(r_tag) = find_tag(&ctx, Some(g_id), msg.author.id.0 as i64, cmd_name) {
+            use commands::tags::{record_tag_use, send_tag};
+
+            record_tag_use(&ctx, r_tag.id);
+
+            let args: Vec<_> = msg.content.split_whitespace().skip(1).map(String::from).collect();
+            send_tag(&ctx, msg, &r_tag, &args);
+        } else {
+            use commands::{aliases::get_alias, tags::{tag_suggestions, AUTO_SUGGESTION_SIMILARITY}};
+
+            // the command might have been meant for an alias instead
+            if get_alias(&ctx, cmd_name, msg.author.id.0 as i64, Some(g_id)).is_none() {
+                if let Some(reply) = tag_suggestions(&ctx, g_id, cmd_name, AUTO_SUGGESTION_SIMILARITY) {
+                    void!(say(msg.channel_id, reply));
+                }
+            }
         }
     }
 }
 
 /// Process possible alias activations
 fn process_alias(ctx: &mut Context, msg: &Message, cmd_name: &str) {
-    use commands::aliases::get_alias;
-
-    if let Some(alias) = get_alias(&ctx, &cmd_name, msg.author.id.0 as i64) {
-        // we need to be careful here, as to not keep the data locked when we dispatch the command
-        let (mut framework, threadpool) = {
-            let lock = ctx.data.lock();
-            let framework = lock.get::<FrameworkContainer>().unwrap().clone();
-            let threadpool = lock.get::<ThreadPoolCache>().unwrap().clone();
-            (framework, threadpool)
-        };
-        let alias_message = format!("generic#{}", alias);
+    use commands::aliases::{check_alias_commands, expand_alias, get_alias, split_command};
 
-        let mut spoof_message = msg.clone();
-        spoof_message.content = alias_message;
+    let u_id = msg.author.id.0 as i64;
+    let g_id = msg.guild_id.map(|g| g.0 as i64);
+    let (prefix, input) = split_command(&msg.content, cmd_name);
+
+    let commands = expand_alias(cmd_name, input, |name| get_alias(&ctx, name, u_id, g_id))
+        .map_err(|e| e.to_string())
+        .and_then(|commands| check_alias_commands(&ctx, &commands).map(|_| commands));
+
+    let commands = match commands {
+        Ok(commands) => commands,
+        Err(e) => {
+            void!(say(msg.channel_id, format!("Couldn't run alias `{}`: {}", cmd_name, e)));
+            return;
+        }
+    };
+
+    if commands.is_empty() {
+        return;
+    }
+
+    // we need to be careful here, as to not keep the data locked when we dispatch the command
+    let mut framework = ctx.data.lock().get::<FrameworkContainer>().unwrap().clone();
+
+    // a pool of one thread runs the commands one after another in the order they were dispatched
+    let threadpool = ThreadPool::new(1);
+
+    // commands are run with the prefix the alias was used with, so the framework checks them
+    // (including their buckets) as normal
+    for command in commands {
+        let mut alias_message = msg.clone();
+        alias_message.content = format!("{}{}", prefix, command);
 
         if let Some(ref mut framework) = *framework.lock() {
-            framework.dispatch(ctx.clone(), spoof_message, &*threadpool.lock(), false);
+            framework.dispatch(ctx.clone(), alias_message, &threadpool, false);
         };
     }
+
+    threadpool.join();
 }
 
 // Our setup stuff
@@ -479,11 +551,17 @@ fn main() {
 
     client.threadpool.set_num_threads(16);
 
+    // filled in by the setup functions as they add command groups
+    client.data.lock().insert::<CommandRegistry>(commands::Commands::default());
+
     let setup_fns = &[
         setup,
         commands::tags::setup_tags,
         commands::admin::setup_admin,
         commands::reminders::setup_reminders,
+        commands::events::setup_events,
+        commands::announcements::setup_announcements,
+        commands::responders::setup_responders,
         commands::markov::setup_markov,
         commands::misc::setup_misc,
         commands::booru::setup_booru,
@@ -506,6 +584,9 @@ fn main() {
         data.insert::<StartTime>(chrono::Utc::now().naive_utc());
         data.insert::<CmdCounter>(Arc::new(RwLock::new(0)));
         data.insert::<PrefixCache>(LruCache::new(1000));
+        data.insert::<ResponderCache>(LruCache::new(1000));
+        data.insert::<ResponderCooldowns>(LruCache::new(10000));
+        data.insert::<PendingAliasImports>(LruCache::new(1000));
         data.insert::<ThreadPoolCache>(Arc::new(Mutex::new(client.threadpool.clone())));
     }
 
@@ -33,15 +33,44 @@ pub struct NewReminder<'a> {
     pub text: &'a str,
     pub started: &'a NaiveDateTime,
     pub when: &'a NaiveDateTime,
+    pub message_id: Option<i64>,
+    pub guild_id: Option<i64>,
+}
+
+#[table_name="reminder_target"]
+#[derive(Insertable)]
+pub struct NewReminderTarget {
+    pub reminder_id: i64,
+    pub target_id: i64,
+    pub is_role: bool,
 }
 
 #[table_name="tag"]
 #[derive(Insertable)]
 pub struct NewTag<'a> {
     pub author_id: i64,
-    pub guild_id: i64,
+    pub guild_id: Option<i64>,
     pub key: &'a str,
     pub text: &'a str,
+    pub alias_of: Option<i64>,
+    pub scope: &'a str,
+}
+
+#[table_name="tag_attachment"]
+#[derive(Insertable)]
+pub struct NewTagAttachment<'a> {
+    pub tag_id: i64,
+    pub filename: &'a str,
+    pub data: &'a [u8],
+}
+
+#[table_name="tag_revision"]
+#[derive(Insertable)]
+pub struct NewTagRevision<'a> {
+    pub tag_id: i64,
+    pub text: &'a str,
+    pub replaced_by: i64,
+    pub replaced_at: &'a NaiveDateTime,
 }
 
 #[table_name="command_alias"]
@@ -52,6 +81,22 @@ pub struct NewCommandAlias<'a> {
     pub alias_value: &'a str,
 }
 
+#[table_name="booru_blacklist"]
+#[derive(Insertable)]
+pub struct NewBooruBlacklist<'a> {
+    pub guild_id: i64,
+    pub tag: &'a str,
+}
+
+#[table_name="guild_command_alias"]
+#[derive(Insertable)]
+pub struct NewGuildCommandAlias<'a> {
+    pub guild_id: i64,
+    pub author_id: i64,
+    pub alias_name: &'a str,
+    pub alias_value: &'a str,
+}
+
 #[table_name="tea_count"]
 #[derive(Insertable)]
 pub struct NewTeaCount {
@@ -66,12 +111,104 @@ pub struct NewBlock {
     pub channel_id: Option<i64>,
 }
 
+#[table_name="guild_event"]
+#[derive(Insertable)]
+pub struct NewGuildEvent<'a> {
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub message_id: i64,
+    pub author_id: i64,
+    pub title: &'a str,
+    pub when: &'a NaiveDateTime,
+}
+
+#[table_name="guild_event_ping"]
+#[derive(Insertable)]
+pub struct NewGuildEventPing<'a> {
+    pub event_id: i64,
+    pub when: &'a NaiveDateTime,
+}
+
+#[table_name="guild_event_rsvp"]
+#[derive(Insertable)]
+pub struct NewGuildEventRsvp {
+    pub event_id: i64,
+    pub user_id: i64,
+}
+
+#[table_name="announcement"]
+#[derive(Insertable)]
+pub struct NewAnnouncement<'a> {
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub author_id: i64,
+    pub text: &'a str,
+    pub is_embed: bool,
+    pub next_run: &'a NaiveDateTime,
+    pub repeat_minutes: Option<i32>,
+}
+
+#[derive(Queryable)]
+pub struct Announcement {
+    pub id: i64,
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub author_id: i64,
+    pub text: String,
+    pub is_embed: bool,
+    pub next_run: NaiveDateTime,
+    pub repeat_minutes: Option<i32>,
+    pub paused: bool,
+}
+
+#[table_name="auto_responder"]
+#[derive(Insertable)]
+pub struct NewAutoResponder<'a> {
+    pub guild_id: i64,
+    pub author_id: i64,
+    pub pattern: &'a str,
+    pub match_kind: &'a str,
+    pub case_sensitive: bool,
+    pub response: &'a str,
+    pub channel_ids: &'a [i64],
+    pub cooldown_seconds: i32,
+}
+
+#[derive(Queryable)]
+pub struct AutoResponder {
+    pub id: i64,
+    pub guild_id: i64,
+    pub author_id: i64,
+    pub pattern: String,
+    pub match_kind: String,
+    pub case_sensitive: bool,
+    pub response: String,
+    pub channel_ids: Vec<i64>,
+    pub cooldown_seconds: i32,
+}
+
 #[derive(Queryable)]
 pub struct Guild {
     pub id: i64,
     pub markov_on: bool,
     pub tag_prefix_on: bool,
     pub commands_from: i64,
+    pub remind_others_perms: i64,
+    pub event_lead_times: Vec<i32>,
+    pub tag_max_per_user: Option<i32>,
+    pub tag_max_per_guild: Option<i32>,
+    pub tag_create_role: Option<i64>,
+}
+
+#[derive(Queryable)]
+pub struct GuildEvent {
+    pub id: i64,
+    pub guild_id: i64,
+    pub channel_id: i64,
+    pub message_id: i64,
+    pub author_id: i64,
+    pub title: String,
+    pub when: NaiveDateTime,
 }
 
 #[derive(Queryable)]
@@ -98,15 +235,47 @@ pub struct Reminder {
     pub text: String,
     pub started: NaiveDateTime,
     pub when: NaiveDateTime,
+    pub message_id: Option<i64>,
+    pub guild_id: Option<i64>,
+}
+
+#[derive(Queryable)]
+pub struct ReminderTarget {
+    pub reminder_id: i64,
+    pub target_id: i64,
+    pub is_role: bool,
 }
 
 #[derive(Queryable)]
 pub struct Tag {
     pub id: i64,
     pub author_id: i64,
-    pub guild_id: i64,
+    pub guild_id: Option<i64>,
     pub key: String,
     pub text: String,
+    pub alias_of: Option<i64>,
+    pub uses: i32,
+    pub last_used: Option<NaiveDateTime>,
+    pub created_at: Option<NaiveDateTime>,
+    pub embed: Option<String>,
+    pub scope: String,
+}
+
+#[derive(Queryable)]
+pub struct TagAttachment {
+    pub id: i64,
+    pub tag_id: i64,
+    pub filename: String,
+    pub data: Vec<u8>,
+}
+
+#[derive(Queryable)]
+pub struct TagRevision {
+    pub id: i64,
+    pub tag_id: i64,
+    pub text: String,
+    pub replaced_by: i64,
+    pub replaced_at: NaiveDateTime,
 }
 
 #[derive(Queryable)]
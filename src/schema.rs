@@ -1,3 +1,31 @@
+table! {
+    announcement (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        channel_id -> Int8,
+        author_id -> Int8,
+        text -> Varchar,
+        is_embed -> Bool,
+        next_run -> Timestamp,
+        repeat_minutes -> Nullable<Int4>,
+        paused -> Bool,
+    }
+}
+
+table! {
+    auto_responder (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        author_id -> Int8,
+        pattern -> Varchar,
+        match_kind -> Varchar,
+        case_sensitive -> Bool,
+        response -> Varchar,
+        channel_ids -> Array<Int8>,
+        cooldown_seconds -> Int4,
+    }
+}
+
 table! {
     blocked_guilds_channels (id) {
         id -> Int4,
@@ -6,6 +34,13 @@ table! {
     }
 }
 
+table! {
+    booru_blacklist (guild_id, tag) {
+        guild_id -> Int8,
+        tag -> Varchar,
+    }
+}
+
 table! {
     command_alias (id) {
         id -> Int8,
@@ -21,6 +56,48 @@ table! {
         markov_on -> Bool,
         tag_prefix_on -> Bool,
         commands_from -> Int8,
+        remind_others_perms -> Int8,
+        event_lead_times -> Array<Int4>,
+        tag_max_per_user -> Nullable<Int4>,
+        tag_max_per_guild -> Nullable<Int4>,
+        tag_create_role -> Nullable<Int8>,
+    }
+}
+
+table! {
+    guild_command_alias (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        author_id -> Int8,
+        alias_name -> Varchar,
+        alias_value -> Varchar,
+    }
+}
+
+table! {
+    guild_event (id) {
+        id -> Int8,
+        guild_id -> Int8,
+        channel_id -> Int8,
+        message_id -> Int8,
+        author_id -> Int8,
+        title -> Varchar,
+        when -> Timestamp,
+    }
+}
+
+table! {
+    guild_event_ping (id) {
+        id -> Int8,
+        event_id -> Int8,
+        when -> Timestamp,
+    }
+}
+
+table! {
+    guild_event_rsvp (event_id, user_id) {
+        event_id -> Int8,
+        user_id -> Int8,
     }
 }
 
@@ -50,6 +127,23 @@ table! {
         text -> Varchar,
         started -> Timestamp,
         when -> Timestamp,
+        message_id -> Nullable<Int8>,
+        guild_id -> Nullable<Int8>,
+    }
+}
+
+table! {
+    reminder_opt_out (reminder_id, user_id) {
+        reminder_id -> Int8,
+        user_id -> Int8,
+    }
+}
+
+table! {
+    reminder_target (reminder_id, target_id) {
+        reminder_id -> Int8,
+        target_id -> Int8,
+        is_role -> Bool,
     }
 }
 
@@ -57,9 +151,34 @@ table! {
     tag (id) {
         id -> Int8,
         author_id -> Int8,
-        guild_id -> Int8,
+        guild_id -> Nullable<Int8>,
         key -> Varchar,
         text -> Varchar,
+        alias_of -> Nullable<Int8>,
+        uses -> Int4,
+        last_used -> Nullable<Timestamp>,
+        created_at -> Nullable<Timestamp>,
+        embed -> Nullable<Varchar>,
+        scope -> Varchar,
+    }
+}
+
+table! {
+    tag_attachment (id) {
+        id -> Int8,
+        tag_id -> Int8,
+        filename -> Varchar,
+        data -> Bytea,
+    }
+}
+
+table! {
+    tag_revision (id) {
+        id -> Int8,
+        tag_id -> Int8,
+        text -> Varchar,
+        replaced_by -> Int8,
+        replaced_at -> Timestamp,
     }
 }
 
@@ -70,17 +189,39 @@ table! {
     }
 }
 
+joinable!(announcement -> guild (guild_id));
+joinable!(auto_responder -> guild (guild_id));
+joinable!(booru_blacklist -> guild (guild_id));
+joinable!(guild_command_alias -> guild (guild_id));
+joinable!(guild_event -> guild (guild_id));
+joinable!(guild_event_ping -> guild_event (event_id));
+joinable!(guild_event_rsvp -> guild_event (event_id));
 joinable!(message -> guild (guild_id));
 joinable!(prefix -> guild (guild_id));
+joinable!(reminder_opt_out -> reminder (reminder_id));
+joinable!(reminder_target -> reminder (reminder_id));
 joinable!(tag -> guild (guild_id));
+joinable!(tag_attachment -> tag (tag_id));
+joinable!(tag_revision -> tag (tag_id));
 
 allow_tables_to_appear_in_same_query!(
+    announcement,
+    auto_responder,
     blocked_guilds_channels,
+    booru_blacklist,
     command_alias,
     guild,
+    guild_command_alias,
+    guild_event,
+    guild_event_ping,
+    guild_event_rsvp,
     message,
     prefix,
     reminder,
+    reminder_opt_out,
+    reminder_target,
     tag,
+    tag_attachment,
+    tag_revision,
     tea_count,
 );
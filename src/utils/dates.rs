@@ -0,0 +1,733 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime};
+use std::cmp::min;
+
+
+#[derive(Debug, Fail, PartialEq)]
+pub enum DateError {
+    #[fail(display = "Could not parse time.")]
+    NoDate,
+    #[fail(display = "Cannot give more than one date.")]
+    MultipleDates,
+    #[fail(display = "Cannot give more than one time of day.")]
+    MultipleTimes,
+    #[fail(display = "That date does not exist.")]
+    InvalidDate,
+    #[fail(display = "That time is too far away.")]
+    OutOfRange,
+    #[fail(display = "That time has already passed.")]
+    InPast,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TokenKind {
+    Word,
+    Num(u32),
+    Punct(char),
+}
+
+
+#[derive(Debug, Clone)]
+struct Token<'a> {
+    kind: TokenKind,
+    raw: &'a str,
+    lower: String,
+    start: usize,
+    end: usize,
+}
+
+
+/// Split text into runs of letters, runs of digits and single punctuation characters.
+fn tokenize(text: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    let mut chars = text.char_indices().peekable();
+
+    while let Some((start, c)) = chars.next() {
+        if c.is_whitespace() {
+            continue;
+        }
+
+        let mut end = start + c.len_utf8();
+
+        if c.is_alphabetic() || c.is_ascii_digit() {
+            let is_digit = c.is_ascii_digit();
+
+            while let Some(&(i, n)) = chars.peek() {
+                let same_class = if is_digit { n.is_ascii_digit() } else { n.is_alphabetic() };
+                if !same_class {
+                    break;
+                }
+                end = i + n.len_utf8();
+                chars.next();
+            }
+        }
+
+        let raw = &text[start..end];
+
+        let kind = if c.is_ascii_digit() {
+            // numbers too large to be useful are left as words
+            raw.parse().map(TokenKind::Num).unwrap_or(TokenKind::Word)
+        } else if c.is_alphabetic() {
+            TokenKind::Word
+        } else {
+            TokenKind::Punct(c)
+        };
+
+        tokens.push(Token { kind, raw, lower: raw.to_lowercase(), start, end });
+    }
+
+    tokens
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Unit {
+    Year,
+    Month,
+    Week,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum DateSpec {
+    /// A number of days from today
+    Days(i64),
+    /// Days from monday
+    Weekday(u32),
+    EndOf(Unit),
+    Calendar { year: Option<i32>, month: u32, day: u32 },
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Component {
+    Delta(i64, Unit),
+    Date(DateSpec),
+    Time(NaiveTime),
+}
+
+
+fn is_word(tokens: &[Token], i: usize, words: &[&str]) -> bool {
+    tokens.get(i).map_or(false, |t| t.kind == TokenKind::Word && words.contains(&t.lower.as_str()))
+}
+
+
+fn is_punct(tokens: &[Token], i: usize, p: char) -> bool {
+    tokens.get(i).map_or(false, |t| t.kind == TokenKind::Punct(p))
+}
+
+
+fn num(tokens: &[Token], i: usize) -> Option<u32> {
+    match tokens.get(i)?.kind {
+        TokenKind::Num(n) => Some(n),
+        _ => None,
+    }
+}
+
+
+fn year(tokens: &[Token], i: usize) -> Option<i32> {
+    let t = tokens.get(i)?;
+    match t.kind {
+        TokenKind::Num(n) if t.raw.len() == 4 => Some(n as i32),
+        _ => None,
+    }
+}
+
+
+/// Skip over an optional word, returning the index after it
+fn skip_word(tokens: &[Token], i: usize, words: &[&str]) -> usize {
+    if is_word(tokens, i, words) { i + 1 } else { i }
+}
+
+
+fn unit(tokens: &[Token], i: usize) -> Option<Unit> {
+    let t = tokens.get(i)?;
+
+    if t.kind != TokenKind::Word {
+        return None;
+    }
+
+    // `M` is months, `m` is minutes
+    if t.raw == "M" {
+        return Some(Unit::Month);
+    }
+
+    let u = match t.lower.as_str() {
+        "y" | "yr" | "yrs" | "year" | "years" => Unit::Year,
+        "mo" | "mos" | "month" | "months" => Unit::Month,
+        "w" | "wk" | "wks" | "week" | "weeks" => Unit::Week,
+        "d" | "day" | "days" => Unit::Day,
+        "h" | "hr" | "hrs" | "hour" | "hours" => Unit::Hour,
+        "m" | "min" | "mins" | "minute" | "minutes" => Unit::Minute,
+        "s" | "sec" | "secs" | "second" | "seconds" => Unit::Second,
+        _ => return None,
+    };
+
+    Some(u)
+}
+
+
+fn month(tokens: &[Token], i: usize) -> Option<u32> {
+    let t = tokens.get(i)?;
+
+    if t.kind != TokenKind::Word {
+        return None;
+    }
+
+    let m = match t.lower.as_str() {
+        "jan" | "january" => 1,
+        "feb" | "february" => 2,
+        "mar" | "march" => 3,
+        "apr" | "april" => 4,
+        "may" => 5,
+        "jun" | "june" => 6,
+        "jul" | "july" => 7,
+        "aug" | "august" => 8,
+        "sep" | "sept" | "september" => 9,
+        "oct" | "october" => 10,
+        "nov" | "november" => 11,
+        "dec" | "december" => 12,
+        _ => return None,
+    };
+
+    Some(m)
+}
+
+
+fn weekday(tokens: &[Token], i: usize) -> Option<u32> {
+    let t = tokens.get(i)?;
+
+    if t.kind != TokenKind::Word {
+        return None;
+    }
+
+    let d = match t.lower.as_str() {
+        "monday" => 0,
+        "tuesday" => 1,
+        "wednesday" => 2,
+        "thursday" => 3,
+        "friday" => 4,
+        "saturday" => 5,
+        "sunday" => 6,
+        _ => return None,
+    };
+
+    Some(d)
+}
+
+
+/// `in 3 hours`, `5m`, `in an hour`
+fn parse_delta(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let has_in = is_word(tokens, i, &["in"]);
+    let i = if has_in { i + 1 } else { i };
+
+    let value = match num(tokens, i) {
+        Some(n) => i64::from(n),
+        // only allow worded numbers after "in", so "have a day off" isn't a date
+        None if has_in => match tokens.get(i)?.lower.as_str() {
+            "a" | "an" | "one" => 1,
+            "two" => 2,
+            "three" => 3,
+            "four" => 4,
+            "five" => 5,
+            "six" => 6,
+            "seven" => 7,
+            "eight" => 8,
+            "nine" => 9,
+            "ten" => 10,
+            _ => return None,
+        },
+        None => return None,
+    };
+
+    let unit = unit(tokens, i + 1)?;
+
+    Some((Component::Delta(value, unit), i + 2))
+}
+
+
+/// `next week`, `next month`, `next year`
+fn parse_next_period(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    if !is_word(tokens, i, &["next"]) {
+        return None;
+    }
+
+    let unit = match tokens.get(i + 1)?.lower.as_str() {
+        "week" => Unit::Week,
+        "month" => Unit::Month,
+        "year" => Unit::Year,
+        _ => return None,
+    };
+
+    Some((Component::Delta(1, unit), i + 2))
+}
+
+
+/// `today`, `tomorrow`
+fn parse_relative_day(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let days = match tokens.get(i)?.lower.as_str() {
+        "today" => 0,
+        "tomorrow" => 1,
+        _ => return None,
+    };
+
+    Some((Component::Date(DateSpec::Days(days)), i + 1))
+}
+
+
+/// `friday`, `on monday`, `next tuesday`
+fn parse_weekday(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let i = skip_word(tokens, i, &["on"]);
+    let i = skip_word(tokens, i, &["next", "this"]);
+
+    let day = weekday(tokens, i)?;
+
+    Some((Component::Date(DateSpec::Weekday(day)), i + 1))
+}
+
+
+/// `end of month`, `by the end of the week`
+fn parse_end_of(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let i = skip_word(tokens, i, &["by", "at", "on"]);
+    let i = skip_word(tokens, i, &["the"]);
+
+    if !is_word(tokens, i, &["end"]) || !is_word(tokens, i + 1, &["of"]) {
+        return None;
+    }
+
+    let i = skip_word(tokens, i + 2, &["the", "this"]);
+
+    let unit = match tokens.get(i)?.lower.as_str() {
+        "week" => Unit::Week,
+        "month" => Unit::Month,
+        "year" => Unit::Year,
+        _ => return None,
+    };
+
+    Some((Component::Date(DateSpec::EndOf(unit)), i + 1))
+}
+
+
+/// `july 4th`, `4th of july`, `on 4 july 2020`
+fn parse_month_day(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let i = skip_word(tokens, i, &["on"]);
+
+    let ordinal = |i: usize| -> Option<(u32, usize)> {
+        let day = num(tokens, i)?;
+        Some((day, skip_word(tokens, i + 1, &["st", "nd", "rd", "th"])))
+    };
+
+    let (month, day, i) = if let Some(m) = month(tokens, i) {
+        let (day, i) = ordinal(i + 1)?;
+        (m, day, i)
+    } else {
+        let (day, i) = ordinal(i)?;
+        let i = skip_word(tokens, i, &["of"]);
+        (month(tokens, i)?, day, i + 1)
+    };
+
+    let (year, i) = if let Some(y) = year(tokens, i) {
+        (Some(y), i + 1)
+    } else if is_punct(tokens, i, ',') && year(tokens, i + 1).is_some() {
+        (year(tokens, i + 1), i + 2)
+    } else {
+        (None, i)
+    };
+
+    Some((Component::Date(DateSpec::Calendar { year, month, day }), i))
+}
+
+
+/// `2019-03-01`
+fn parse_iso_date(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let i = skip_word(tokens, i, &["on"]);
+
+    let year = year(tokens, i)?;
+
+    if !is_punct(tokens, i + 1, '-') || !is_punct(tokens, i + 3, '-') {
+        return None;
+    }
+
+    let month = num(tokens, i + 2)?;
+    let day = num(tokens, i + 4)?;
+
+    Some((Component::Date(DateSpec::Calendar { year: Some(year), month, day }), i + 5))
+}
+
+
+/// `at 3pm`, `15:30`, `at noon`, `at 5`
+fn parse_time(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let has_at = is_word(tokens, i, &["at"]);
+    let i = if has_at { i + 1 } else { i };
+
+    if is_word(tokens, i, &["noon", "midday"]) {
+        return Some((Component::Time(NaiveTime::from_hms(12, 0, 0)), i + 1));
+    }
+
+    if is_word(tokens, i, &["midnight"]) {
+        return Some((Component::Time(NaiveTime::from_hms(0, 0, 0)), i + 1));
+    }
+
+    let hour = num(tokens, i)?;
+    let mut i = i + 1;
+
+    let has_minutes = is_punct(tokens, i, ':') && num(tokens, i + 1).is_some();
+
+    let minute = if has_minutes {
+        let m = num(tokens, i + 1)?;
+        i += 2;
+        m
+    } else {
+        0
+    };
+
+    let hour = if is_word(tokens, i, &["am", "pm"]) {
+        if hour == 0 || hour > 12 {
+            return None;
+        }
+
+        let is_pm = tokens[i].lower == "pm";
+        i += 1;
+
+        match (hour, is_pm) {
+            (12, false) => 0,
+            (12, true) => 12,
+            (h, false) => h,
+            (h, true) => h + 12,
+        }
+    } else if has_at || has_minutes {
+        hour
+    } else {
+        // a bare number isn't a time
+        return None;
+    };
+
+    NaiveTime::from_hms_opt(hour, minute, 0).map(|t| (Component::Time(t), i))
+}
+
+
+fn parse_component(tokens: &[Token], i: usize) -> Option<(Component, usize)> {
+    let parsers: &[fn(&[Token], usize) -> Option<(Component, usize)>] = &[
+        parse_end_of,
+        parse_iso_date,
+        parse_month_day,
+        parse_weekday,
+        parse_relative_day,
+        parse_next_period,
+        parse_delta,
+        parse_time,
+    ];
+
+    parsers.iter().filter_map(|p| p(tokens, i)).next()
+}
+
+
+/// Parse as many components as possible starting from a token, returning them and the index after the last
+fn parse_expr(tokens: &[Token], start: usize) -> Option<(Vec<Component>, usize)> {
+    let (first, mut end) = parse_component(tokens, start)?;
+    let mut components = vec![first];
+
+    loop {
+        let mut i = end;
+
+        // separators are only consumed if another component follows them
+        while is_word(tokens, i, &["and"]) || is_punct(tokens, i, ',') {
+            i += 1;
+        }
+
+        match parse_component(tokens, i) {
+            Some((c, e)) => {
+                components.push(c);
+                end = e;
+            },
+            None => break,
+        }
+    }
+
+    Some((components, end))
+}
+
+
+fn days_in_month(year: i32, month: u32) -> Option<u32> {
+    let (next_year, next_month) = if month == 12 { (year + 1, 1) } else { (year, month + 1) };
+
+    let first = NaiveDate::from_ymd_opt(year, month, 1)?;
+    let next = NaiveDate::from_ymd_opt(next_year, next_month, 1)?;
+
+    Some(next.signed_duration_since(first).num_days() as u32)
+}
+
+
+/// Add months to a time, clamping the day to the end of the resulting month.
+fn add_months(t: NaiveDateTime, months: i64) -> Result<NaiveDateTime, DateError> {
+    let total = i64::from(t.year()) * 12 + i64::from(t.month0()) + months;
+
+    if total / 12 > 100_000 {
+        return Err(DateError::OutOfRange);
+    }
+
+    let year = (total / 12) as i32;
+    let month = (total % 12) as u32 + 1;
+
+    let day = min(t.day(), days_in_month(year, month).ok_or(DateError::OutOfRange)?);
+
+    NaiveDate::from_ymd_opt(year, month, day)
+        .map(|d| d.and_time(t.time()))
+        .ok_or(DateError::OutOfRange)
+}
+
+
+fn add_delta(t: NaiveDateTime, value: i64, unit: Unit) -> Result<NaiveDateTime, DateError> {
+    let secs = match unit {
+        Unit::Year => return add_months(t, value * 12),
+        Unit::Month => return add_months(t, value),
+        Unit::Week => 7 * 24 * 60 * 60,
+        Unit::Day => 24 * 60 * 60,
+        Unit::Hour => 60 * 60,
+        Unit::Minute => 60,
+        Unit::Second => 1,
+    };
+
+    t.checked_add_signed(Duration::seconds(value * secs))
+     .ok_or(DateError::OutOfRange)
+}
+
+
+fn apply_date(base: NaiveDateTime, spec: DateSpec, time: Option<NaiveTime>) -> Result<NaiveDateTime, DateError> {
+    let current_day = base.weekday().num_days_from_monday();
+
+    let res = match spec {
+        DateSpec::Days(n) => base + Duration::days(n),
+        DateSpec::Weekday(day) => {
+            let mut delta = (day + 7 - current_day) % 7;
+
+            // today only counts if the time given is still to come
+            if delta == 0 && !time.map_or(false, |t| t > base.time()) {
+                delta = 7;
+            }
+
+            base + Duration::days(i64::from(delta))
+        },
+        DateSpec::EndOf(Unit::Week) => base + Duration::days(i64::from(6 - current_day)),
+        DateSpec::EndOf(Unit::Month) => {
+            let last = days_in_month(base.year(), base.month()).ok_or(DateError::OutOfRange)?;
+            base.with_day(last).ok_or(DateError::InvalidDate)?
+        },
+        DateSpec::EndOf(_) => base
+            .with_month(12).ok_or(DateError::InvalidDate)?
+            .with_day(31).ok_or(DateError::InvalidDate)?,
+        DateSpec::Calendar { year, month, day } => {
+            let date = NaiveDate::from_ymd_opt(year.unwrap_or_else(|| base.year()), month, day)
+                .ok_or(DateError::InvalidDate)?;
+
+            let at = date.and_time(time.unwrap_or_else(|| NaiveTime::from_hms(0, 0, 0)));
+
+            // dates without a year that have passed are next year
+            if year.is_none() && at <= base {
+                NaiveDate::from_ymd_opt(base.year() + 1, month, day)
+                    .ok_or(DateError::InvalidDate)?
+                    .and_hms(0, 0, 0)
+            } else {
+                date.and_hms(0, 0, 0)
+            }
+        },
+    };
+
+    Ok(res)
+}
+
+
+fn evaluate(base: NaiveDateTime, components: &[Component]) -> Result<NaiveDateTime, DateError> {
+    let mut date = None;
+    let mut time = None;
+    let mut deltas = Vec::new();
+
+    for &c in components {
+        match c {
+            Component::Delta(v, u) => deltas.push((v, u)),
+            Component::Date(_) if date.is_some() => return Err(DateError::MultipleDates),
+            Component::Date(d) => date = Some(d),
+            Component::Time(_) if time.is_some() => return Err(DateError::MultipleTimes),
+            Component::Time(t) => time = Some(t),
+        }
+    }
+
+    let mut result = match date {
+        Some(d) => apply_date(base, d, time)?,
+        None => base,
+    };
+
+    for &(v, u) in &deltas {
+        result = add_delta(result, v, u)?;
+    }
+
+    if let Some(t) = time {
+        result = result.date().and_time(t);
+
+        // a time on its own means the next time it comes around
+        if date.is_none() && deltas.is_empty() && result <= base {
+            result += Duration::days(1);
+        }
+    }
+
+    if result < base {
+        return Err(DateError::InPast);
+    }
+
+    Ok(result)
+}
+
+
+/// Parse a text message into a datetime and the remaining string.
+///
+/// The first run of words that makes up a date is used, all times are UTC.
+pub fn recognise_date(base_time: NaiveDateTime, text: &str) -> Result<(NaiveDateTime, String), DateError> {
+    let tokens = tokenize(text);
+
+    for start in 0..tokens.len() {
+        let (components, end) = try_opt_continue!(parse_expr(&tokens, start));
+
+        let when = evaluate(base_time, &components)?;
+
+        let mut before = text[..tokens[start].start].trim_end();
+        let mut after = text[tokens[end - 1].end..].trim_start();
+
+        // drop quotes that were wrapped around the date
+        if before.ends_with('"') && after.starts_with('"') {
+            before = &before[..before.len() - 1];
+            after = &after[1..];
+        }
+
+        let remaining = format!("{} {}", before, after)
+            .trim_matches(|c: char| c.is_whitespace() || c == ',')
+            .to_owned();
+
+        return Ok((when, remaining));
+    }
+
+    Err(DateError::NoDate)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    lazy_static! {
+        // A wednesday
+        static ref BASE_TIME: NaiveDateTime = time("2019-03-06 12:00:00");
+    }
+
+    #[test]
+    fn test_recognise_dates() {
+        let cases = &[
+            // deltas
+            ("in 3 hours do x", "2019-03-06 15:00:00", "do x"),
+            ("3h do x", "2019-03-06 15:00:00", "do x"),
+            ("5 mins do x", "2019-03-06 12:05:00", "do x"),
+            ("30 s do x", "2019-03-06 12:00:30", "do x"),
+            ("in an hour do x", "2019-03-06 13:00:00", "do x"),
+            ("in two days do x", "2019-03-08 12:00:00", "do x"),
+            ("in 2 weeks and 3 days do x", "2019-03-23 12:00:00", "do x"),
+            ("in 1 week, 2 days do x", "2019-03-15 12:00:00", "do x"),
+            ("1h30m do x", "2019-03-06 13:30:00", "do x"),
+            ("2M do x", "2019-05-06 12:00:00", "do x"),
+            ("in 1 month do x", "2019-04-06 12:00:00", "do x"),
+            ("1y do x", "2020-03-06 12:00:00", "do x"),
+            ("next week do x", "2019-03-13 12:00:00", "do x"),
+            ("next month do x", "2019-04-06 12:00:00", "do x"),
+            // relative days
+            ("tomorrow do x", "2019-03-07 12:00:00", "do x"),
+            ("today at 5pm do x", "2019-03-06 17:00:00", "do x"),
+            ("tomorrow at noon do x", "2019-03-07 12:00:00", "do x"),
+            // weekdays
+            ("friday do x", "2019-03-08 12:00:00", "do x"),
+            ("friday at 3pm do x", "2019-03-08 15:00:00", "do x"),
+            ("on monday do x", "2019-03-11 12:00:00", "do x"),
+            ("next tuesday do x", "2019-03-12 12:00:00", "do x"),
+            ("wednesday do x", "2019-03-13 12:00:00", "do x"),
+            ("wednesday at 6pm do x", "2019-03-06 18:00:00", "do x"),
+            ("wednesday at 9am do x", "2019-03-13 09:00:00", "do x"),
+            // times of day
+            ("at 3pm do x", "2019-03-06 15:00:00", "do x"),
+            ("at 3:30 pm do x", "2019-03-06 15:30:00", "do x"),
+            ("at 9am do x", "2019-03-07 09:00:00", "do x"),
+            ("at 15:30 do x", "2019-03-06 15:30:00", "do x"),
+            ("at 5 do x", "2019-03-07 05:00:00", "do x"),
+            ("at midnight do x", "2019-03-07 00:00:00", "do x"),
+            ("do x at 12am", "2019-03-07 00:00:00", "do x"),
+            // calendar dates
+            ("july 4th do x", "2019-07-04 00:00:00", "do x"),
+            ("on july 4th do x", "2019-07-04 00:00:00", "do x"),
+            ("4th of july do x", "2019-07-04 00:00:00", "do x"),
+            ("on 4 july 2020 do x", "2020-07-04 00:00:00", "do x"),
+            ("july 4th, 2020 do x", "2020-07-04 00:00:00", "do x"),
+            ("jan 1st do x", "2020-01-01 00:00:00", "do x"),
+            ("march 10 at 9am do x", "2019-03-10 09:00:00", "do x"),
+            ("2019-12-25 do x", "2019-12-25 00:00:00", "do x"),
+            ("on 2019-12-25 at 8am do x", "2019-12-25 08:00:00", "do x"),
+            // end of periods
+            ("end of month do x", "2019-03-31 12:00:00", "do x"),
+            ("by the end of the week do x", "2019-03-10 12:00:00", "do x"),
+            ("end of year do x", "2019-12-31 12:00:00", "do x"),
+            ("end of month at 5pm do x", "2019-03-31 17:00:00", "do x"),
+            // mixing
+            ("in 2 days at 9am do x", "2019-03-08 09:00:00", "do x"),
+            ("next friday at 3pm do x", "2019-03-08 15:00:00", "do x"),
+            // placement of the date in the message
+            ("do x in 3 hours", "2019-03-06 15:00:00", "do x"),
+            ("buy 2 apples tomorrow", "2019-03-07 12:00:00", "buy 2 apples"),
+            ("have a day off tomorrow", "2019-03-07 12:00:00", "have a day off"),
+            ("\"3 hours\" do x", "2019-03-06 15:00:00", "do x"),
+            ("tomorrow, do x", "2019-03-07 12:00:00", "do x"),
+            ("TOMORROW AT 3PM do x", "2019-03-07 15:00:00", "do x"),
+            ("in 3 hours", "2019-03-06 15:00:00", ""),
+        ];
+
+        for &(input, expected, remaining) in cases {
+            assert_eq!(recognise_date(*BASE_TIME, input),
+                       Ok((time(expected), remaining.to_owned())),
+                       "parsing: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_recognise_date_errors() {
+        let cases = &[
+            ("do x", DateError::NoDate),
+            ("", DateError::NoDate),
+            ("at 13pm do x", DateError::NoDate),
+            ("tomorrow friday do x", DateError::MultipleDates),
+            ("at 3pm at 4pm do x", DateError::MultipleTimes),
+            ("feb 30 do x", DateError::InvalidDate),
+            ("2019-02-30 do x", DateError::InvalidDate),
+            ("4000000000 years do x", DateError::OutOfRange),
+            ("today at 9am do x", DateError::InPast),
+            ("2018-12-25 do x", DateError::InPast),
+            ("on 4 july 2018 do x", DateError::InPast),
+        ];
+
+        for &(input, ref expected) in cases {
+            assert_eq!(recognise_date(*BASE_TIME, input).as_ref().err(), Some(expected),
+                       "parsing: {}", input);
+        }
+    }
+
+    #[test]
+    fn test_month_clamping() {
+        let base = time("2019-01-31 12:00:00");
+
+        assert_eq!(recognise_date(base, "in 1 month do x"),
+                   Ok((time("2019-02-28 12:00:00"), "do x".to_owned())));
+        assert_eq!(recognise_date(base, "end of month do x"),
+                   Ok((time("2019-01-31 12:00:00"), "do x".to_owned())));
+    }
+}
@@ -0,0 +1,500 @@
+use chrono::{Datelike, Duration, NaiveDate, NaiveDateTime, TimeZone};
+use chrono_tz::Tz;
+use itertools::Itertools;
+
+
+/// A single VEVENT, only holding the parts we care about
+#[derive(Debug, Clone, PartialEq)]
+pub struct CalendarEvent {
+    pub uid: String,
+    pub start: NaiveDateTime,
+    pub summary: String,
+    pub rrule: Option<String>,
+    /// Offset of the first alarm from the start of the event
+    pub alarm: Option<Duration>,
+}
+
+
+fn escape_text(s: &str) -> String {
+    s.replace('\\', "\\\\")
+     .replace(';', "\\;")
+     .replace(',', "\\,")
+     .replace('\n', "\\n")
+}
+
+
+fn unescape_text(s: &str) -> String {
+    let mut res = String::with_capacity(s.len());
+    let mut chars = s.chars();
+
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            res.push(c);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n') | Some('N') => res.push('\n'),
+            Some(c) => res.push(c),
+            None => (),
+        }
+    }
+
+    res
+}
+
+
+/// Fold a content line so that no line is longer than 75 octets
+fn fold_line(line: &str) -> String {
+    let mut res = String::new();
+    let mut len = 0;
+
+    for c in line.chars() {
+        if len + c.len_utf8() > 75 {
+            res.push_str("\r\n ");
+            len = 1;
+        }
+        res.push(c);
+        len += c.len_utf8();
+    }
+
+    res
+}
+
+
+fn format_time(t: &NaiveDateTime) -> String {
+    t.format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+
+/// Build a VCALENDAR from a list of events, times are written as UTC
+pub fn write_calendar(events: &[CalendarEvent], now: &NaiveDateTime) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//genericbot//reminders//EN".to_owned(),
+    ];
+
+    for event in events {
+        lines.push("BEGIN:VEVENT".to_owned());
+        lines.push(format!("UID:{}", event.uid));
+        lines.push(format!("DTSTAMP:{}", format_time(now)));
+        lines.push(format!("DTSTART:{}", format_time(&event.start)));
+        lines.push(format!("SUMMARY:{}", escape_text(&event.summary)));
+
+        if let Some(ref rrule) = event.rrule {
+            lines.push(format!("RRULE:{}", rrule));
+        }
+
+        lines.push("BEGIN:VALARM".to_owned());
+        lines.push("ACTION:DISPLAY".to_owned());
+        lines.push(format!("DESCRIPTION:{}", escape_text(&event.summary)));
+        lines.push(format!("TRIGGER:{}", format_duration(&event.alarm.unwrap_or_else(Duration::zero))));
+        lines.push("END:VALARM".to_owned());
+        lines.push("END:VEVENT".to_owned());
+    }
+
+    lines.push("END:VCALENDAR".to_owned());
+
+    lines.iter().map(|l| fold_line(l)).join("\r\n") + "\r\n"
+}
+
+
+fn format_duration(d: &Duration) -> String {
+    let (sign, secs) = if *d < Duration::zero() {
+        ("-", -d.num_seconds())
+    } else {
+        ("", d.num_seconds())
+    };
+
+    format!("{}PT{}S", sign, secs)
+}
+
+
+/// The most seconds a `Duration` can hold without panicking
+const MAX_DURATION_SECS: i64 = i64::max_value() / 1000;
+
+/// Recurrence rules with a larger INTERVAL are ignored
+const MAX_INTERVAL: i64 = 10_000;
+
+
+/// `n` lots of `unit` seconds, `None` if it doesn't fit in a `Duration`
+fn checked_seconds(n: i64, unit: i64) -> Option<Duration> {
+    let secs = n.checked_mul(unit)?;
+
+    if secs > MAX_DURATION_SECS || secs < -MAX_DURATION_SECS {
+        return None;
+    }
+
+    Some(Duration::seconds(secs))
+}
+
+
+/// Parse an iCalendar duration value, such as `-PT15M` or `P1DT2H`
+fn parse_duration(s: &str) -> Option<Duration> {
+    let (negative, s) = match s.chars().next()? {
+        '-' => (true, &s[1..]),
+        '+' => (false, &s[1..]),
+        _ => (false, s),
+    };
+
+    if !s.starts_with('P') {
+        return None;
+    }
+
+    let mut total = Duration::zero();
+    let mut num = String::new();
+    let mut in_time = false;
+
+    for c in s[1..].chars() {
+        match c {
+            '0'..='9' => num.push(c),
+            'T' => in_time = true,
+            _ => {
+                let val = num.parse::<i64>().ok()?;
+                num.clear();
+
+                let unit = match (c, in_time) {
+                    ('W', false) => 7 * 24 * 60 * 60,
+                    ('D', false) => 24 * 60 * 60,
+                    ('H', true) => 60 * 60,
+                    ('M', true) => 60,
+                    ('S', true) => 1,
+                    _ => return None,
+                };
+
+                total = total.checked_add(&checked_seconds(val, unit)?)?;
+            },
+        }
+    }
+
+    if !num.is_empty() {
+        return None;
+    }
+
+    Some(if negative { -total } else { total })
+}
+
+
+/// Parse a DATE or DATE-TIME value as UTC
+fn parse_utc_time(value: &str) -> Option<NaiveDateTime> {
+    let value = value.trim_end_matches('Z');
+
+    if value.len() == 8 {
+        return NaiveDate::parse_from_str(value, "%Y%m%d").ok().map(|d| d.and_hms(0, 0, 0));
+    }
+
+    NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S").ok()
+}
+
+
+/// Parse a DTSTART value, converting times given with a TZID to UTC.
+///
+/// Floating times without a TZID are read as UTC.
+fn parse_time(value: &str, tzid: Option<&str>) -> Result<Option<NaiveDateTime>, String> {
+    let time = match parse_utc_time(value) {
+        Some(t) => t,
+        None => return Ok(None),
+    };
+
+    let tzid = match tzid {
+        Some(z) if !value.ends_with('Z') => z,
+        _ => return Ok(Some(time)),
+    };
+
+    let zone = tzid
+        .parse::<Tz>()
+        .map_err(|_| format!("the time zone {} is not known", tzid))?;
+
+    zone.from_local_datetime(&time)
+        .earliest()
+        .map(|t| Some(t.naive_utc()))
+        .ok_or_else(|| format!("{} does not exist in {}", time, tzid))
+}
+
+
+/// Unfold the content lines of a calendar, splitting each into its name, TZID parameter and value
+fn content_lines(cal: &str) -> Vec<(String, Option<String>, String)> {
+    let mut unfolded: Vec<String> = Vec::new();
+
+    for line in cal.lines() {
+        let line = line.trim_end_matches('\r');
+
+        if line.starts_with(' ') || line.starts_with('\t') {
+            if let Some(last) = unfolded.last_mut() {
+                last.push_str(&line[1..]);
+            }
+        } else if !line.is_empty() {
+            unfolded.push(line.to_owned());
+        }
+    }
+
+    unfolded
+        .into_iter()
+        .filter_map(|l| {
+            let pos = l.find(':')?;
+            let (name, value) = l.split_at(pos);
+            let mut params = name.split(';');
+            let name = params.next().unwrap().to_uppercase();
+            // other parameters, such as VALUE, don't change how we read values
+            let tzid = params
+                .find(|p| p.to_uppercase().starts_with("TZID="))
+                .map(|p| p[5..].trim_matches('"').to_owned());
+            Some((name, tzid, value[1..].to_owned()))
+        })
+        .collect()
+}
+
+
+/// Parse all the VEVENTs from a calendar, events without a start time are skipped.
+///
+/// Events with a start time that can't be converted to UTC are given as errors naming the event.
+pub fn parse_calendar(cal: &str) -> Vec<Result<CalendarEvent, String>> {
+    let mut events = Vec::new();
+
+    let mut in_event = false;
+    let mut in_alarm = false;
+
+    let mut uid = String::new();
+    let mut start = Ok(None);
+    let mut summary = String::new();
+    let mut rrule = None;
+    let mut alarm = None;
+
+    for (name, tzid, value) in content_lines(cal) {
+        match (name.as_str(), value.as_str()) {
+            ("BEGIN", "VEVENT") => {
+                in_event = true;
+                uid.clear();
+                start = Ok(None);
+                summary.clear();
+                rrule = None;
+                alarm = None;
+            },
+            ("END", "VEVENT") => {
+                in_event = false;
+                match start {
+                    Err(ref e) => events.push(Err(format!("{}: {}", summary, e))),
+                    Ok(Some(start)) => events.push(Ok(CalendarEvent {
+                        uid: uid.clone(),
+                        start,
+                        summary: summary.clone(),
+                        rrule: rrule.clone(),
+                        alarm,
+                    })),
+                    Ok(None) => (),
+                }
+            },
+            ("BEGIN", "VALARM") => in_alarm = true,
+            ("END", "VALARM") => in_alarm = false,
+            _ if !in_event => (),
+            ("TRIGGER", v) if in_alarm => {
+                if alarm.is_none() {
+                    alarm = parse_duration(v);
+                }
+            },
+            _ if in_alarm => (),
+            ("UID", v) => uid = v.to_owned(),
+            ("DTSTART", v) => start = parse_time(v, tzid.as_ref().map(String::as_str)),
+            ("SUMMARY", v) => summary = unescape_text(v),
+            ("RRULE", v) => rrule = Some(v.to_owned()),
+            _ => (),
+        }
+    }
+
+    events
+}
+
+
+fn add_months(t: NaiveDateTime, months: i64) -> Option<NaiveDateTime> {
+    let month0 = i64::from(t.month0()).checked_add(months)?;
+    let year = i64::from(t.year()) + month0 / 12;
+
+    if year > i64::from(i32::max_value()) {
+        return None;
+    }
+
+    t.with_day(1)?
+     .with_year(year as i32)?
+     .with_month0((month0 % 12) as u32)?
+     .with_day(t.day())
+}
+
+
+/// Find the first occurrence of an event that is after `after`.
+///
+/// Only the FREQ, INTERVAL, COUNT and UNTIL parts of a recurrence rule are understood.
+pub fn next_occurrence(event: &CalendarEvent, after: &NaiveDateTime) -> Option<NaiveDateTime> {
+    if event.start > *after {
+        return Some(event.start);
+    }
+
+    let rrule = event.rrule.as_ref()?;
+
+    let mut freq = None;
+    let mut interval = 1;
+    let mut count = None;
+    let mut until = None;
+
+    for part in rrule.split(';') {
+        let mut kv = part.splitn(2, '=');
+        match (kv.next(), kv.next()) {
+            (Some("FREQ"), Some(v)) => freq = Some(v.to_owned()),
+            (Some("INTERVAL"), Some(v)) => interval = v.parse::<i64>().ok()?,
+            (Some("COUNT"), Some(v)) => count = Some(v.parse::<i64>().ok()?),
+            (Some("UNTIL"), Some(v)) => until = parse_utc_time(v),
+            _ => (),
+        }
+    }
+
+    if interval <= 0 || interval > MAX_INTERVAL {
+        return None;
+    }
+
+    let step = |n: i64| -> Option<NaiveDateTime> {
+        let n = n.checked_mul(interval)?;
+        let unit = match freq.as_ref()?.as_str() {
+            "SECONDLY" => 1,
+            "MINUTELY" => 60,
+            "HOURLY" => 60 * 60,
+            "DAILY" => 24 * 60 * 60,
+            "WEEKLY" => 7 * 24 * 60 * 60,
+            "MONTHLY" => return add_months(event.start, n),
+            "YEARLY" => return add_months(event.start, n.checked_mul(12)?),
+            _ => return None,
+        };
+
+        event.start.checked_add_signed(checked_seconds(n, unit)?)
+    };
+
+    // bound the search so that silly rules can't spin forever
+    for n in 1..100_000 {
+        if count.map_or(false, |c| n >= c) {
+            return None;
+        }
+
+        // months without the day (such as the 31st) are skipped
+        let occurrence = match step(n) {
+            Some(t) => t,
+            None if freq.as_ref().map_or(false, |f| f == "MONTHLY" || f == "YEARLY") => continue,
+            None => return None,
+        };
+
+        if until.map_or(false, |u| occurrence > u) {
+            return None;
+        }
+
+        if occurrence > *after {
+            return Some(occurrence);
+        }
+    }
+
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn time(s: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let event = CalendarEvent {
+            uid: "reminder-1@genericbot".to_owned(),
+            start: time("2019-03-01 10:00:00"),
+            summary: "tea, biscuits; and a very long summary that needs to be folded onto a second line".to_owned(),
+            rrule: Some("FREQ=WEEKLY".to_owned()),
+            alarm: Some(Duration::zero()),
+        };
+
+        let cal = write_calendar(&[event.clone()], &time("2019-02-01 00:00:00"));
+
+        assert!(cal.lines().all(|l| l.len() <= 76));
+        assert_eq!(parse_calendar(&cal), vec![Ok(event)]);
+    }
+
+    #[test]
+    fn test_parse_calendar() {
+        let cal = concat!(
+            "BEGIN:VCALENDAR\r\n",
+            "BEGIN:VEVENT\r\n",
+            "UID:abc\r\n",
+            "DTSTART;TZID=Europe/London:20190701T100000\r\n",
+            "SUMMARY:Meeting\\nwith people\r\n",
+            "BEGIN:VALARM\r\n",
+            "TRIGGER:-PT15M\r\n",
+            "END:VALARM\r\n",
+            "END:VEVENT\r\n",
+            "BEGIN:VEVENT\r\n",
+            "DTSTART;VALUE=DATE:20190302\r\n",
+            "SUMMARY:All day\r\n",
+            "END:VEVENT\r\n",
+            "BEGIN:VEVENT\r\n",
+            "SUMMARY:No start\r\n",
+            "END:VEVENT\r\n",
+            "BEGIN:VEVENT\r\n",
+            "DTSTART;TZID=\"GMT Standard Time\":20190303T100000\r\n",
+            "SUMMARY:Local\r\n",
+            "END:VEVENT\r\n",
+            "END:VCALENDAR\r\n");
+
+        let mut events = parse_calendar(cal);
+
+        assert_eq!(events.pop(), Some(Err("Local: the time zone GMT Standard Time is not known".to_owned())));
+
+        let events: Vec<_> = events.into_iter().map(Result::unwrap).collect();
+
+        assert_eq!(events.len(), 2);
+        assert_eq!(events[0].start, time("2019-07-01 09:00:00"));
+        assert_eq!(events[0].summary, "Meeting\nwith people");
+        assert_eq!(events[0].alarm, Some(Duration::minutes(-15)));
+        assert_eq!(events[1].start, time("2019-03-02 00:00:00"));
+        assert_eq!(events[1].alarm, None);
+    }
+
+    #[test]
+    fn test_next_occurrence() {
+        let mut event = CalendarEvent {
+            uid: String::new(),
+            start: time("2019-01-31 10:00:00"),
+            summary: String::new(),
+            rrule: None,
+            alarm: None,
+        };
+
+        let after = time("2019-02-10 00:00:00");
+
+        assert_eq!(next_occurrence(&event, &after), None);
+
+        event.rrule = Some("FREQ=WEEKLY;INTERVAL=2".to_owned());
+        assert_eq!(next_occurrence(&event, &after), Some(time("2019-02-14 10:00:00")));
+
+        event.rrule = Some("FREQ=MONTHLY".to_owned());
+        assert_eq!(next_occurrence(&event, &after), Some(time("2019-03-31 10:00:00")));
+
+        event.rrule = Some("FREQ=DAILY;COUNT=5".to_owned());
+        assert_eq!(next_occurrence(&event, &after), None);
+
+        event.rrule = Some("FREQ=DAILY;UNTIL=20190212T000000Z".to_owned());
+        assert_eq!(next_occurrence(&event, &after), Some(time("2019-02-10 10:00:00")));
+
+        event.rrule = Some("FREQ=YEARLY;INTERVAL=999999999999".to_owned());
+        assert_eq!(next_occurrence(&event, &after), None);
+
+        event.rrule = Some(format!("FREQ=WEEKLY;INTERVAL={}", MAX_INTERVAL + 1));
+        assert_eq!(next_occurrence(&event, &after), None);
+    }
+
+    #[test]
+    fn test_parse_duration() {
+        assert_eq!(parse_duration("-PT15M"), Some(Duration::minutes(-15)));
+        assert_eq!(parse_duration("P1DT2H"), Some(Duration::hours(26)));
+        assert_eq!(parse_duration("P1H"), None);
+        assert_eq!(parse_duration("-P99999999999999W"), None);
+        assert_eq!(parse_duration("P99999999999999999999S"), None);
+    }
+}
@@ -18,8 +18,11 @@ use std::fmt::Display;
 
 #[macro_use]
 pub mod macros;
+pub mod dates;
+pub mod ical;
 pub mod markov;
 pub mod pagination;
+pub mod template;
 
 pub fn names_for_members<U, G>(u_ids: &[U], g_id: G) -> Vec<String>
 where
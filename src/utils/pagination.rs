@@ -120,3 +120,48 @@ impl<T> Paginate for T {
         }
     }
 }
+
+
+/// Parse the arguments of a command listing one of several lists: an optional list name followed by a page.
+///
+/// The first list is used when no name is given.
+pub fn parse_list_args<'a>(input: &str, lists: &[&'a str]) -> Result<(&'a str, i64), String> {
+    let mut words = input.split_whitespace().peekable();
+
+    let list = match words.peek() {
+        Some(word) if word.parse::<i64>().is_err() => {
+            let list = lists
+                .iter()
+                .find(|l| l.eq_ignore_ascii_case(word))
+                .ok_or_else(|| format!("Pick one of: {}.", lists.join(", ")))?;
+            words.next();
+            *list
+        },
+        _ => lists[0],
+    };
+
+    let page = words.next().and_then(|p| p.parse::<i64>().ok()).unwrap_or(1);
+
+    if page <= 0 {
+        return Err("That page does not exist.".to_owned());
+    }
+
+    Ok((list, page))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_list_args() {
+        let lists = &["mine", "server"];
+
+        assert_eq!(parse_list_args("", lists), Ok(("mine", 1)));
+        assert_eq!(parse_list_args("3", lists), Ok(("mine", 3)));
+        assert_eq!(parse_list_args("Server 2", lists), Ok(("server", 2)));
+        assert!(parse_list_args("other", lists).is_err());
+        assert!(parse_list_args("server 0", lists).is_err());
+    }
+}
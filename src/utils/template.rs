@@ -0,0 +1,172 @@
+use rand::{seq::SliceRandom, Rng};
+use super::normalize;
+
+
+/// Rendered templates are cut off at this many characters
+pub const MAX_OUTPUT: usize = 2000;
+
+/// The largest span a `{rand:a-b}` can cover
+const MAX_RAND_SPAN: i64 = 1_000_000_000;
+
+
+/// Values that can be substituted into a template
+pub struct TemplateVars<'a> {
+    pub user: &'a str,
+    pub user_id: u64,
+    pub channel: &'a str,
+    pub guild: &'a str,
+    pub args: &'a [String],
+}
+
+
+/// Evaluate a single `{...}` expression, returns `None` if the expression is not known.
+fn eval_expr<R: Rng>(expr: &str, vars: &TemplateVars, rng: &mut R) -> Option<String> {
+    match expr {
+        "user" => return Some(vars.user.to_owned()),
+        "user.mention" => return Some(format!("<@{}>", vars.user_id)),
+        "channel" => return Some(vars.channel.to_owned()),
+        "guild" => return Some(vars.guild.to_owned()),
+        "args" => return Some(vars.args.join(" ")),
+        _ => (),
+    }
+
+    let (name, param) = {
+        let mut parts = expr.splitn(2, ':');
+        (parts.next()?, parts.next()?)
+    };
+
+    match name {
+        "arg" => {
+            let n = param.trim().parse::<usize>().ok()?;
+
+            if n == 0 {
+                return None;
+            }
+
+            Some(vars.args.get(n - 1).cloned().unwrap_or_default())
+        },
+        "choose" => {
+            let options: Vec<_> = param.split('|').collect();
+            options.choose(rng).map(|s| (*s).to_owned())
+        },
+        "rand" => {
+            // skip the first character so that the lower bound can be negative
+            let split = param.char_indices().skip(1).find(|&(_, c)| c == '-')?.0;
+            let low = param[..split].trim().parse::<i64>().ok()?;
+            let high = param[split + 1..].trim().parse::<i64>().ok()?;
+
+            if low > high || high.checked_sub(low)? > MAX_RAND_SPAN {
+                return None;
+            }
+
+            Some(rng.gen_range(low, high.checked_add(1)?).to_string())
+        },
+        _ => None,
+    }
+}
+
+
+/// Render a tag template.
+///
+/// Unknown or unterminated expressions are left as they are, substituted values
+/// are never evaluated again.
+pub fn render<R: Rng>(template: &str, vars: &TemplateVars, rng: &mut R) -> String {
+    let mut output = String::new();
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = match rest[1..].find(|c| c == '{' || c == '}') {
+            Some(i) if rest.as_bytes()[i + 1] == b'}' => i + 1,
+            // unterminated or nested, emit the brace and keep going
+            _ => {
+                output.push('{');
+                rest = &rest[1..];
+                continue;
+            },
+        };
+
+        match eval_expr(&rest[1..end], vars, rng) {
+            Some(value) => output.push_str(&value),
+            None => output.push_str(&rest[..=end]),
+        }
+
+        rest = &rest[end + 1..];
+
+        if output.len() > MAX_OUTPUT * 4 {
+            break;
+        }
+    }
+
+    output.push_str(rest);
+
+    normalize(&output).chars().take(MAX_OUTPUT).collect()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, SeedableRng};
+
+    fn vars(args: &[String]) -> TemplateVars {
+        TemplateVars {
+            user: "ben",
+            user_id: 1234,
+            channel: "general",
+            guild: "the guild",
+            args,
+        }
+    }
+
+    #[test]
+    fn test_render_vars() {
+        let args = vec!["one".to_owned(), "two".to_owned()];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        let cases = &[
+            ("hi {user}", "hi ben"),
+            ("hi {user.mention}", "hi <@1234>"),
+            ("{channel} in {guild}", "general in the guild"),
+            ("{args}!", "one two!"),
+            ("{arg:2} {arg:1} {arg:3}.", "two one ."),
+            ("{arg:0} {unknown} {rand:6-1}", "{arg:0} {unknown} {rand:6-1}"),
+            ("{user {user}} {", "{user ben} {"),
+            ("no braces", "no braces"),
+        ];
+
+        for &(template, expected) in cases {
+            assert_eq!(render(template, &vars(&args), &mut rng), expected, "rendering: {}", template);
+        }
+    }
+
+    #[test]
+    fn test_render_random() {
+        let mut rng = StdRng::seed_from_u64(0);
+
+        for _ in 0..50 {
+            let choice = render("{choose:a|b|c}", &vars(&[]), &mut rng);
+            assert!(["a", "b", "c"].contains(&choice.as_str()));
+
+            let roll = render("{rand:1-6}", &vars(&[]), &mut rng).parse::<i64>().unwrap();
+            assert!(roll >= 1 && roll <= 6);
+
+            let roll = render("{rand:-3--1}", &vars(&[]), &mut rng).parse::<i64>().unwrap();
+            assert!(roll >= -3 && roll <= -1);
+        }
+    }
+
+    #[test]
+    fn test_render_safety() {
+        let args = vec!["@everyone".to_owned()];
+        let mut rng = StdRng::seed_from_u64(0);
+
+        assert_eq!(render("{args}", &vars(&args), &mut rng), "@\u{200B}everyone");
+        assert_eq!(render("{{args}}", &vars(&args), &mut rng), "{@\u{200B}everyone}");
+
+        let long = "{user}".repeat(1000);
+        assert_eq!(render(&long, &vars(&[]), &mut rng).chars().count(), MAX_OUTPUT);
+    }
+}